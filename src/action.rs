@@ -0,0 +1,46 @@
+// Everything the player can ask the game window to do. Buttons and keyboard shortcuts
+// both carry one of these, so renaming a button's label does not change what it does.
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Roll,
+    Reroll,
+    ChooseDie(u32), // Move by one of the dice rolled under the choose_die rule
+    Reset,
+    Difficulty, // Step to the next difficulty and start a new board with it
+    Save,
+    Load,
+    Dig,
+    Heatmap,
+    Undo,
+    Players,
+    Settings,
+    Network,
+    Quit,
+}
+
+impl Action {
+    // Actions that change the game for everyone, only the host of a network game can do these
+    pub fn host_only(self) -> bool {
+        matches!(
+            self,
+            Action::Reset
+                | Action::Difficulty
+                | Action::Load
+                | Action::Undo
+                | Action::Players
+                | Action::Settings
+        )
+    }
+}
+
+// What the buttons on the settings, players and lobby screens do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenAction {
+    Apply, // Keep the changes and close the screen
+    Cancel,
+    Host,
+    Join,
+    Leave, // End the network game
+}
@@ -0,0 +1,130 @@
+// Decision making for computer controlled players
+use crate::game_state::GameState;
+use crate::objects::Bot;
+use curv::arithmetic::Converter;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+// Every choice the rules can offer a player goes through a Strategy, so house rules
+// with choices can be played by bots. New rules add a method here.
+pub trait Strategy {
+    // Index into dice of the die to move by, used by the choose_die rule
+    fn choose_die(&self, state: &GameState, player: usize, dice: &[u32]) -> usize;
+
+    // Whether to spend a re-roll token on the coming roll
+    fn use_reroll(&self, state: &GameState, player: usize) -> bool;
+}
+
+pub struct GreedyStrategy;
+
+impl Strategy for GreedyStrategy {
+    fn choose_die(&self, state: &GameState, player: usize, dice: &[u32]) -> usize {
+        // Try every die on a quiet copy of the game and keep the best outcome
+        (0..dice.len())
+            .max_by_key(|indx| {
+                let mut trial = state.clone();
+                trial.quiet = true;
+                let rolls_again = trial.advance_player(dice[*indx], player as i32);
+                let user = &trial.users[player];
+                let mut score = user.position as i64 * 2;
+                if rolls_again {
+                    score += 1;
+                }
+                score - user.skip_turns as i64 * 4
+            })
+            .unwrap_or(0)
+    }
+
+    fn use_reroll(&self, state: &GameState, player: usize) -> bool {
+        // Worth it when a snake could be reached with this roll and there is no shield for it
+        let position = state.users[player].position;
+        let reach = position + state.settings.rules.die_sides;
+        state.users[player].shields == 0
+            && state
+                .arrows
+                .iter()
+                .any(|(from, to)| to < from && *from > position && *from <= reach)
+    }
+}
+
+pub struct RandomStrategy;
+
+impl RandomStrategy {
+    // Seeded from the game seed and the turn, so a game always plays out the same way
+    fn rng(state: &GameState, decision: &[u8]) -> StdRng {
+        let digest = Sha256::new()
+            .chain(state.settings.game_id.to_bytes())
+            .chain(state.turn.to_le_bytes())
+            .chain(decision)
+            .finalize();
+        let mut rng_seed = [0u8; 32];
+        rng_seed.copy_from_slice(&digest);
+        StdRng::from_seed(rng_seed)
+    }
+}
+
+impl Strategy for RandomStrategy {
+    fn choose_die(&self, state: &GameState, _player: usize, dice: &[u32]) -> usize {
+        // The dice go into the seed too, a roll again in the same turn is a new choice
+        let decision: Vec<u8> = dice.iter().flat_map(|die| die.to_le_bytes()).collect();
+        Self::rng(state, &decision).random_range(0..dice.len().max(1))
+    }
+
+    fn use_reroll(&self, state: &GameState, _player: usize) -> bool {
+        Self::rng(state, b"reroll").random_bool(0.5)
+    }
+}
+
+pub fn strategy_for(bot: Bot) -> Box<dyn Strategy> {
+    match bot {
+        Bot::Greedy => Box::new(GreedyStrategy),
+        Bot::Random => Box::new(RandomStrategy),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use curv::BigInt;
+
+    // Player 0 on square 1 of a board with a snake from 3 and a ladder from 4
+    fn snake_and_ladder() -> GameState {
+        let mut state = GameState::with_seed(BigInt::from(1));
+        state.arrows = vec![(3, 1), (4, 20)];
+        state.special_squares.clear();
+        state.new_game = false;
+        state.users[0].position = 1;
+        state
+    }
+
+    #[test]
+    fn test_greedy_picks_the_better_die() {
+        let state = snake_and_ladder();
+        assert_eq!(GreedyStrategy.choose_die(&state, 0, &[2, 3]), 1);
+        assert_eq!(GreedyStrategy.choose_die(&state, 0, &[3, 2]), 0);
+        // Trying the dice leaves nothing behind
+        assert_eq!(state.users[0].position, 1);
+        assert!(state.history.is_empty());
+    }
+
+    #[test]
+    fn test_random_choices_follow_the_game_seed() {
+        let state = snake_and_ladder();
+        let dice = [1, 2, 3, 4];
+        let choices = |state: &GameState| -> Vec<usize> {
+            (0..20)
+                .map(|turn| {
+                    let mut state = state.clone();
+                    state.turn = turn;
+                    RandomStrategy.choose_die(&state, 0, &dice)
+                })
+                .collect()
+        };
+        assert_eq!(choices(&state), choices(&state));
+
+        let mut other = state.clone();
+        other.settings.game_id = BigInt::from(2);
+        assert_ne!(choices(&state), choices(&other));
+    }
+}
@@ -0,0 +1,302 @@
+// How long games on a board take, worked out exactly instead of by playing them.
+// A player's square after each turn is a Markov chain: from every square each face of
+// the die leads to one square once overshoot, arrows and moving square effects are
+// applied. Effects that change whose turn it is (skip, roll again, swap) are left out,
+// so are shields and the choose_die rule.
+use crate::game_state::GameState;
+use crate::objects::SquareEffect;
+use std::fmt;
+
+// The chain is followed until the chance of still playing drops below this
+const UNFINISHED: f64 = 1e-6;
+const MAX_TURNS: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardAnalysis {
+    // Chance of having finished by each turn, index 0 is after the first turn
+    pub finish_curve: Vec<f64>,
+    pub expected_turns: f64,
+    // Expected number of turns ending on each square, index is the square number
+    pub visits: Vec<f64>,
+    pub converged: bool, // False if games could still be going after MAX_TURNS
+}
+
+impl BoardAnalysis {
+    // The first turn by which a game has finished with at least this chance
+    pub fn turns_for(&self, probability: f64) -> Option<usize> {
+        self.finish_curve
+            .iter()
+            .position(|finished| *finished >= probability)
+            .map(|indx| indx + 1)
+    }
+
+    // Chance of each seat winning a game of players, when seats take turns in order.
+    // Seat i wins on its turn k if it finishes then, the seats before it have not
+    // finished in k turns and the seats after it have not finished in k - 1.
+    pub fn win_chances(&self, players: usize) -> Vec<f64> {
+        let unfinished = |turns: usize| match turns {
+            0 => 1.0,
+            turns => 1.0 - self.finish_curve[turns - 1],
+        };
+        (0..players)
+            .map(|seat| {
+                (1..=self.finish_curve.len())
+                    .map(|turn| {
+                        let finishes = unfinished(turn - 1) - unfinished(turn);
+                        finishes
+                            * unfinished(turn).powi(seat as i32)
+                            * unfinished(turn - 1).powi((players - seat - 1) as i32)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    // How much more likely the first seat is to win than a fair share, in percent
+    pub fn first_player_advantage(&self, players: usize) -> f64 {
+        match self.win_chances(players).first() {
+            Some(chance) => (chance - 1.0 / players as f64) * 100.0,
+            None => 0.0,
+        }
+    }
+
+    // How far the board is from a fairness target, 0 when it meets it
+    pub fn fairness_miss(
+        &self,
+        players: usize,
+        min_turns: u32,
+        max_turns: u32,
+        max_advantage: u32,
+    ) -> f64 {
+        let turns = if self.expected_turns < min_turns as f64 {
+            min_turns as f64 - self.expected_turns
+        } else {
+            (self.expected_turns - max_turns as f64).max(0.0)
+        };
+        let advantage = (self.first_player_advantage(players) - max_advantage as f64).max(0.0);
+        turns + advantage
+    }
+
+    // The squares turns end on most, busiest first
+    pub fn most_visited(&self, count: usize) -> Vec<(u32, f64)> {
+        let mut squares: Vec<(u32, f64)> = self
+            .visits
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(square, visits)| (square as u32, *visits))
+            .collect();
+        squares.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        squares.truncate(count);
+        squares
+    }
+}
+
+impl fmt::Display for BoardAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Expected turns: {:.1}", self.expected_turns)?;
+        for probability in [0.25, 0.5, 0.75, 0.9, 0.99] {
+            match self.turns_for(probability) {
+                Some(turns) => writeln!(
+                    f,
+                    "{:.0}% of games done by turn {}",
+                    probability * 100.0,
+                    turns
+                )?,
+                None => writeln!(f, "{:.0}% of games never done", probability * 100.0)?,
+            }
+        }
+        writeln!(f, "Most visited squares:")?;
+        for (square, visits) in self.most_visited(5) {
+            writeln!(f, "  {}: {:.2} times a game", square, visits)?;
+        }
+        if !self.converged {
+            writeln!(
+                f,
+                "Games can run past {} turns, numbers are cut off",
+                MAX_TURNS
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Row for each square of where one turn from there can end and with what chance.
+// Square 0 is off the board before the first move, the last square is absorbing.
+pub fn transition_matrix(state: &GameState) -> Vec<Vec<(usize, f64)>> {
+    let last_square = state.last_square();
+    let die_sides = state.settings.rules.die_sides.max(1);
+    let chance = 1.0 / die_sides as f64;
+
+    (0..=last_square)
+        .map(|square| {
+            if square == last_square {
+                return vec![(square as usize, 1.0)];
+            }
+            let mut row: Vec<(usize, f64)> = Vec::new();
+            for value in 1..=die_sides {
+                let to = landing(state, square, value) as usize;
+                match row.iter_mut().find(|(next, _)| *next == to) {
+                    Some((_, p)) => *p += chance,
+                    None => row.push((to, chance)),
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+// Where a player on the square ends up after moving by value, as advance_player does it
+fn landing(state: &GameState, square: u32, value: u32) -> u32 {
+    let last_square = state.last_square();
+    let position = state
+        .settings
+        .rules
+        .overshoot
+        .land(square, value, last_square);
+    let position = follow_arrows(state, position);
+
+    // Effects only trigger when the player actually moved onto the square
+    if position == square {
+        return position;
+    }
+    match state.square_effect(position) {
+        Some(SquareEffect::Teleport(target)) => follow_arrows(state, target),
+        Some(SquareEffect::Bonus(extra)) if position + extra <= last_square => {
+            follow_arrows(state, position + extra)
+        }
+        _ => position,
+    }
+}
+
+fn follow_arrows(state: &GameState, mut position: u32) -> u32 {
+    for (from, to) in &state.arrows {
+        if position == *from {
+            position = *to;
+        }
+    }
+    position
+}
+
+pub fn analyze(state: &GameState) -> BoardAnalysis {
+    let matrix = transition_matrix(state);
+    let last_square = matrix.len() - 1;
+
+    // Everyone starts off the board
+    let mut distribution = vec![0.0; matrix.len()];
+    distribution[0] = 1.0;
+
+    let mut finish_curve = Vec::new();
+    let mut visits = vec![0.0; matrix.len()];
+    let mut expected_turns = 0.0;
+    while finish_curve.len() < MAX_TURNS {
+        // Every turn that starts unfinished counts towards the game's length
+        expected_turns += 1.0 - distribution[last_square];
+
+        let mut next = vec![0.0; matrix.len()];
+        for (square, chance) in distribution.iter().enumerate() {
+            if *chance > 0.0 {
+                for (to, p) in &matrix[square] {
+                    next[*to] += chance * p;
+                }
+            }
+        }
+        distribution = next;
+
+        for (square, chance) in distribution.iter().enumerate().take(last_square) {
+            visits[square] += chance;
+        }
+        finish_curve.push(distribution[last_square]);
+        if 1.0 - distribution[last_square] < UNFINISHED {
+            break;
+        }
+    }
+
+    BoardAnalysis {
+        converged: 1.0 - distribution[last_square] < UNFINISHED,
+        finish_curve,
+        expected_turns,
+        visits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Overshoot;
+    use curv::BigInt;
+
+    fn plain_board(grid_size: u32) -> GameState {
+        let mut state = GameState::with_seed(BigInt::from(1));
+        state.grid_size = grid_size;
+        state.arrows.clear();
+        state.special_squares.clear();
+        state
+    }
+
+    #[test]
+    fn test_rows_are_distributions() {
+        let state = GameState::with_seed(BigInt::from(9));
+        for row in transition_matrix(&state) {
+            let total: f64 = row.iter().map(|(_, p)| p).sum();
+            assert!((total - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_expected_turns_on_a_plain_board() {
+        // On 2x2 with Finish any roll of 4 - s or more ends the game from square s
+        let mut state = plain_board(2);
+        state.settings.rules.overshoot = Overshoot::Finish;
+        state.settings.rules.die_sides = 6;
+        let analysis = analyze(&state);
+        assert!(analysis.converged);
+        assert!((analysis.finish_curve[0] - 0.5).abs() < 1e-9);
+
+        // Expected turns from each square, Es = 1 + the chances of not finishing times E
+        let e3 = 1.0;
+        let e2 = 1.0 + e3 / 6.0;
+        let e1 = 1.0 + (e2 + e3) / 6.0;
+        let e0 = 1.0 + (e1 + e2 + e3) / 6.0;
+        assert!((analysis.expected_turns - e0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_bounces_stay_on_the_board() {
+        // A die that can carry a player past the finish and back beyond the start
+        let mut state = plain_board(2);
+        state.settings.rules.overshoot = Overshoot::Bounce;
+        state.settings.rules.die_sides = 12;
+        for row in transition_matrix(&state) {
+            assert!(row.iter().all(|(to, _)| *to <= 4));
+        }
+        assert!(analyze(&state).converged);
+    }
+
+    #[test]
+    fn test_snakes_make_games_longer() {
+        let plain = analyze(&plain_board(10));
+        let mut state = plain_board(10);
+        state.arrows = vec![(99, 2), (98, 3), (97, 4)];
+        let snakes = analyze(&state);
+
+        assert!(snakes.expected_turns > plain.expected_turns);
+        assert_eq!(snakes.visits[99], 0.0); // Nobody stays on a snake's head
+        assert!(snakes.visits[2] > plain.visits[2]);
+    }
+
+    #[test]
+    fn test_first_player_has_the_edge() {
+        let analysis = analyze(&GameState::with_seed(BigInt::from(5)));
+        let chances = analysis.win_chances(3);
+        assert!((chances.iter().sum::<f64>() - 1.0).abs() < 1e-4);
+        assert!(chances[0] > chances[1] && chances[1] > chances[2]);
+
+        // Going first is worth something on any board
+        let edge = analysis.first_player_advantage(3);
+        assert!(edge > 0.0);
+        assert_eq!(analysis.fairness_miss(3, 1, 1000, 50), 0.0);
+        assert!(analysis.fairness_miss(3, 1, 1000, 0) >= edge - 1e-9);
+        assert!(analysis.fairness_miss(3, 1000, 2000, 50) > 0.0);
+    }
+}
@@ -0,0 +1,235 @@
+// Subcommands that run without opening a window
+use crate::analysis;
+use crate::game_state::{GameState, MAX_DIE_SIDES};
+use crate::objects::{Bot, GameSettings};
+use crate::render;
+use crate::simulation::{self, SimulationConfig};
+use curv::BigInt;
+use curv::arithmetic::{Converter, Zero};
+use std::error::Error;
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+const USAGE: &str = "usage:
+  simulate [--seed N] [--players N] [--games N] [--bot greedy|random]
+  analyze [--seed N] [--die N] [--players N]
+  render [--seed N] [--width N] [--height N] <out.png>
+Games have 1 to 4 players, dice 1 to 20 sides and pictures are 64 to 8192 pixels a side.
+Seeds of any length can be given, short ones are spread out to a full seed";
+
+// Pictures too small to show the board, or too big to hold in memory, are refused
+const PICTURE_SIZES: RangeInclusive<u32> = 64..=8192;
+
+// As many players as the settings screen offers
+fn max_players() -> usize {
+    GameSettings::default().max_players as usize
+}
+
+// Runs the subcommand in args, None when there is none and the window should open
+pub fn run(args: &[String]) -> Option<Result<(), Box<dyn Error>>> {
+    let (command, rest) = args.split_first()?;
+    let result = match command.as_str() {
+        "simulate" => simulate(rest),
+        "render" => render(rest),
+        "analyze" => analyze(rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command \"{}\"\n{}", command, USAGE).into()),
+    };
+    Some(result)
+}
+
+// Options given as --name value, anything else is a positional argument
+struct Options {
+    named: Vec<(String, String)>,
+    positional: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut options = Options {
+            named: Vec::new(),
+            positional: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args.next().ok_or(format!("--{} needs a value", name))?;
+                    options.named.push((name.to_string(), value.clone()));
+                }
+                None => options.positional.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    fn get<T: FromStr>(&self, name: &str, default: T) -> Result<T, Box<dyn Error>> {
+        match self.named.iter().rev().find(|(n, _)| n == name) {
+            Some((_, value)) => value
+                .parse()
+                .map_err(|_| format!("bad value \"{}\" for --{}", value, name).into()),
+            None => Ok(default),
+        }
+    }
+
+    // Like get, but the value has to be in range
+    fn get_in<T: FromStr + PartialOrd + Display>(
+        &self,
+        name: &str,
+        default: T,
+        range: RangeInclusive<T>,
+    ) -> Result<T, Box<dyn Error>> {
+        let value = self.get(name, default)?;
+        if !range.contains(&value) {
+            return Err(format!(
+                "--{} must be from {} to {}\n{}",
+                name,
+                range.start(),
+                range.end(),
+                USAGE
+            )
+            .into());
+        }
+        Ok(value)
+    }
+
+    // --seed as a whole number of any length, spread out to a full seed when short
+    fn seed(&self) -> Result<Option<BigInt>, Box<dyn Error>> {
+        match self.named.iter().rev().find(|(n, _)| n == "seed") {
+            Some((_, value)) => match BigInt::from_str_radix(value, 10) {
+                Ok(seed) if seed >= BigInt::zero() => Ok(Some(GameState::full_seed(seed))),
+                _ => Err(format!("bad value \"{}\" for --seed", value).into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn check(&self, known: &[&str]) -> Result<(), Box<dyn Error>> {
+        match self
+            .named
+            .iter()
+            .find(|(n, _)| !known.contains(&n.as_str()))
+        {
+            Some((name, _)) => Err(format!("unknown option --{}\n{}", name, USAGE).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn simulate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = Options::parse(args)?;
+    options.check(&["seed", "players", "games", "bot"])?;
+
+    let defaults = SimulationConfig::default();
+    let bot = match options.get("bot", "greedy".to_string())?.as_str() {
+        "greedy" => Bot::Greedy,
+        "random" => Bot::Random,
+        other => return Err(format!("unknown bot \"{}\"", other).into()),
+    };
+    let config = SimulationConfig {
+        seed: options.seed()?.unwrap_or(defaults.seed),
+        players: options.get_in("players", defaults.players, 1..=max_players())?,
+        games: options.get("games", defaults.games)?,
+        bot,
+    };
+
+    println!(
+        "Simulating {} games of {} {:?} players on board {}",
+        config.games, config.players, config.bot, config.seed
+    );
+    print!("{}", simulation::simulate(&config));
+    Ok(())
+}
+
+// Prints how long games on the seed's board take for one player
+fn analyze(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = Options::parse(args)?;
+    options.check(&["seed", "die", "players"])?;
+    let players = options.get_in("players", 2, 1..=max_players())?;
+
+    let seed = options.seed()?;
+    let mut state =
+        GameState::with_seed(seed.unwrap_or_else(|| GameState::full_seed(BigInt::from(1))));
+    state.settings.rules.die_sides =
+        options.get_in("die", state.settings.rules.die_sides, 1..=MAX_DIE_SIDES)?;
+    println!(
+        "Board {} with a {} sided die, {} overshoot",
+        state.settings.game_id, state.settings.rules.die_sides, state.settings.rules.overshoot
+    );
+    let analysis = analysis::analyze(&state);
+    print!("{}", analysis);
+    for (seat, chance) in analysis.win_chances(players).iter().enumerate() {
+        println!(
+            "Player {} of {} wins {:.1}%",
+            seat + 1,
+            players,
+            chance * 100.0
+        );
+    }
+    Ok(())
+}
+
+// Writes a picture of a new game on the seed's board
+fn render(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = Options::parse(args)?;
+    options.check(&["seed", "width", "height"])?;
+    let path = options.positional.first().ok_or(USAGE)?;
+    let width = options.get_in("width", 1280, PICTURE_SIZES)?;
+    let height = options.get_in("height", 800, PICTURE_SIZES)?;
+
+    // A random board unless a seed is given
+    let state = match options.seed()? {
+        Some(seed) => GameState::with_seed(seed),
+        None => GameState::new(),
+    };
+    let image = render::render(&state, width, height)?;
+    render::save_png(&image, path)?;
+    println!(
+        "Saved {}x{} picture of board {} to {}",
+        width, height, state.settings.game_id, path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_option(value: &str) -> Result<Option<BigInt>, Box<dyn Error>> {
+        Options::parse(&["--seed".to_string(), value.to_string()])?.seed()
+    }
+
+    #[test]
+    fn test_short_seeds_get_special_squares() {
+        let seed = seed_option("42").unwrap().unwrap();
+        assert_eq!(seed, GameState::full_seed(BigInt::from(42)));
+        assert!(!GameState::with_seed(seed).special_squares.is_empty());
+
+        // A full seed is used as it is
+        let full = GameState::full_seed(BigInt::from(7)).to_string();
+        assert_eq!(seed_option(&full).unwrap().unwrap().to_string(), full);
+
+        assert!(seed_option("forty two").is_err());
+        assert!(seed_option("-1").is_err());
+    }
+
+    #[test]
+    fn test_sizes_out_of_range_are_refused() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let out = std::env::temp_dir().join("cli_refused.png");
+        let out = out.to_str().unwrap();
+
+        for (width, height) in [("0", "0"), ("400", "10"), ("9000", "800")] {
+            assert!(render(&args(&["--width", width, "--height", height, out])).is_err());
+        }
+        assert!(analyze(&args(&["--die", "1000000"])).is_err());
+        assert!(analyze(&args(&["--die", "0"])).is_err());
+        assert!(analyze(&args(&["--players", "0"])).is_err());
+        assert!(simulate(&args(&["--players", "1000"])).is_err());
+        assert!(!std::path::Path::new(out).exists());
+    }
+}
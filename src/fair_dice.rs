@@ -0,0 +1,156 @@
+// File: src/fair_dice.rs
+// Commit-reveal dice for network games. Every player picks a secret nonce and sends only
+// its hash. Once all hashes are in, the nonces are revealed and combined into a seed,
+// and the turn's dice are drawn from that seed. No one can pick the seed alone, and
+// everyone can check the reveals against the commitments and replay the turn.
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Nonce = [u8; 32];
+pub type Commitment = [u8; 32];
+pub type Seed = [u8; 32];
+
+pub fn new_nonce() -> Nonce {
+    let mut nonce = [0u8; 32];
+    rand::rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+// The roll id is part of the hash so a commitment cannot be replayed for another roll
+pub fn commit(roll_id: u64, nonce: &Nonce) -> Commitment {
+    let mut hasher = Sha256::new();
+    hasher.update(roll_id.to_be_bytes());
+    hasher.update(nonce);
+    hasher.finalize().into()
+}
+
+pub fn verify_reveal(roll_id: u64, nonce: &Nonce, commitment: &Commitment) -> bool {
+    commit(roll_id, nonce) == *commitment
+}
+
+// Combines the reveals in commitment order, one honest random nonce is enough for a fair seed
+pub fn combine(roll_id: u64, reveals: &[Nonce]) -> Seed {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&roll_id.to_be_bytes())
+        .expect("HMAC accepts keys of any length");
+    for nonce in reveals {
+        mac.update(nonce);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+// Checks every reveal against its commitment and returns the seed for the roll.
+// Once the commitments are fixed every one of them has to be revealed, otherwise whoever
+// collects the reveals could leave one out after seeing it and pick between two seeds.
+pub fn verify_round(
+    roll_id: u64,
+    commitments: &[Commitment],
+    reveals: &[Nonce],
+) -> Result<Seed, String> {
+    if commitments.len() != reveals.len() {
+        return Err(format!(
+            "{} reveals for {} commitments",
+            reveals.len(),
+            commitments.len()
+        ));
+    }
+
+    for (indx, (commitment, nonce)) in commitments.iter().zip(reveals).enumerate() {
+        if !verify_reveal(roll_id, nonce, commitment) {
+            return Err(format!("reveal {} does not match its commitment", indx));
+        }
+    }
+    if reveals.is_empty() {
+        return Err("no reveals".to_string());
+    }
+    Ok(combine(roll_id, reveals))
+}
+
+// Deterministic dice drawn from a seed, all rolls of one turn come from the same stream
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FairDice {
+    seed: Seed,
+    count: u64, // Draws taken so far
+}
+
+impl FairDice {
+    pub fn new(seed: Seed) -> Self {
+        Self { seed, count: 0 }
+    }
+
+    // Value from 1 to sides
+    pub fn roll(&mut self, sides: u32) -> u32 {
+        let sides = sides.max(1) as u64;
+        // Draws past the largest multiple of sides are thrown away so every face is equally likely
+        let limit = u64::MAX - u64::MAX % sides;
+        loop {
+            let draw = self.next_u64();
+            if draw < limit {
+                return (draw % sides) as u32 + 1;
+            }
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.seed).expect("HMAC accepts keys of any length");
+        mac.update(&self.count.to_be_bytes());
+        self.count += 1;
+
+        let bytes = mac.finalize().into_bytes();
+        let mut draw = [0u8; 8];
+        draw.copy_from_slice(&bytes[..8]);
+        u64::from_be_bytes(draw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reveal_matches_commitment() {
+        let nonce = new_nonce();
+        let commitment = commit(7, &nonce);
+        assert!(verify_reveal(7, &nonce, &commitment));
+        // Same nonce for another roll, or a different nonce, does not match
+        assert!(!verify_reveal(8, &nonce, &commitment));
+        assert!(!verify_reveal(7, &new_nonce(), &commitment));
+    }
+
+    #[test]
+    fn test_verify_round() {
+        let nonces = [new_nonce(), new_nonce(), new_nonce()];
+        let commitments: Vec<Commitment> = nonces.iter().map(|n| commit(3, n)).collect();
+        let reveals = nonces.to_vec();
+
+        let seed = verify_round(3, &commitments, &reveals).unwrap();
+        assert_eq!(seed, combine(3, &nonces));
+
+        // Leaving out a reveal that was committed to is not allowed
+        assert!(verify_round(3, &commitments, &reveals[..2]).is_err());
+        assert!(verify_round(3, &commitments[..2], &reveals[..2]).is_ok());
+
+        // A swapped nonce is caught
+        let mut tampered = reveals.clone();
+        tampered[1] = new_nonce();
+        assert!(verify_round(3, &commitments, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_fair_dice_are_repeatable_and_in_range() {
+        let seed = combine(1, &[new_nonce()]);
+        let mut first = FairDice::new(seed);
+        let mut second = FairDice::new(seed);
+
+        let mut seen = [false; 6];
+        for _ in 0..200 {
+            let value = first.roll(6);
+            assert_eq!(value, second.roll(6));
+            assert!((1..=6).contains(&value));
+            seen[value as usize - 1] = true;
+        }
+        assert!(seen.iter().all(|face| *face));
+    }
+}
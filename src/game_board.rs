@@ -1,6 +1,7 @@
 use crate::drawable::Drawable;
+use crate::heatmap;
 //use crate::game_state;
-use crate::objects::{Arrow, GameSquare};
+use crate::objects::{Arrow, GameSquare, SquareEffect};
 //use std::collections::HashMap;
 //use winit::window::Window;
 //use tiny_skia::{Color, Pixmap};
@@ -26,6 +27,7 @@ pub enum GameBoard {
 #[allow(unused_variables)]
 #[allow(unreachable_patterns)]
 impl GameBoard {
+    #[allow(clippy::too_many_arguments)]
     pub fn init(
         &mut self,
         board_padding: i32,
@@ -34,6 +36,7 @@ impl GameBoard {
         spacing: i32,
         colors: Vec<u32>,
         added_arrows: &mut Vec<(u32, u32)>,
+        special_squares: &[(u32, SquareEffect)],
     ) {
         match self {
             GameBoard::SquareBoard { squares, arrows } => {
@@ -43,7 +46,10 @@ impl GameBoard {
 
                 println!(
                     "Initializing SquareBoard with board size: {}, grid_size: {}, spacing: {}, arrows: {}",
-                    board_size, grid_count, spacing, added_arrows.len()
+                    board_size,
+                    grid_count,
+                    spacing,
+                    added_arrows.len()
                 );
 
                 let sq_size = (board_size / grid_count) - (spacing * 2);
@@ -73,6 +79,12 @@ impl GameBoard {
                     }
                 }
 
+                for (square_number, effect) in special_squares {
+                    if let Some(square) = squares.get_mut((*square_number - 1) as usize) {
+                        square.set_effect(*effect);
+                    }
+                }
+
                 for (arrow_x, arrow_y) in added_arrows {
                     let thickness = 7.0;
                     let color = 0x5F505FF0; // Red color for the arrow
@@ -116,18 +128,30 @@ impl GameBoard {
             GameBoard::EinsteinTileBoard { tiles, arrows } => None,
         }
     }
-}
 
-// Implement methods for the GameBoard enum
-impl Drawable for GameBoard {
-    // Method to draw the board (console-based for simplicity)
-    fn draw(&self, pixmap: &mut Pixmap) {
+    // Draws the board with the squares tinted by their count, index is the square number.
+    // The busiest square gets the full colour, arrows stay on top.
+    pub fn draw_with_heat(&self, pixmap: &mut Pixmap, heat: Option<&[f64]>) {
         match self {
             GameBoard::SquareBoard { squares, arrows } => {
                 for square in squares {
                     square.draw(pixmap);
                 }
 
+                if let Some(heat) = heat {
+                    // The square colours are washed out so the tint reads as heat
+                    for square in squares {
+                        square.draw_tint(pixmap, 0xFFFFFFB4);
+                    }
+                    let busiest = heat.iter().copied().fold(0.0, f64::max);
+                    for square in squares {
+                        let count = heat.get(square.id as usize).copied().unwrap_or(0.0);
+                        if busiest > 0.0 && count > 0.0 {
+                            square.draw_tint(pixmap, heatmap::heat_color(count / busiest));
+                        }
+                    }
+                }
+
                 for arrow in arrows {
                     arrow.draw(pixmap);
                 }
@@ -142,3 +166,11 @@ impl Drawable for GameBoard {
         }
     }
 }
+
+// Implement methods for the GameBoard enum
+impl Drawable for GameBoard {
+    // Method to draw the board (console-based for simplicity)
+    fn draw(&self, pixmap: &mut Pixmap) {
+        self.draw_with_heat(pixmap, None);
+    }
+}
@@ -1,8 +1,20 @@
 use ab_glyph::{Font, FontArc, Glyph, PxScale};
-use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+use tiny_skia::{
+    Color, FillRule, GradientStop, LinearGradient, Paint, PathBuilder, Pixmap, Point, Rect,
+    SpreadMode, Stroke, Transform,
+};
 
+use crate::action::Action;
+use crate::analysis::{self, BoardAnalysis};
 use crate::drawable::Drawable;
 use crate::font_list;
+use crate::game_state::GameState;
+use crate::heatmap::{HeatSource, heat_color};
+use crate::layout::{Align, Bounds, Direction, Item, Stack};
+use crate::miner::SearchEvent;
+use crate::objects::shapes::push_text;
+use crate::objects::{RuleSet, SquareEffect, User};
+use std::time::Instant;
 
 // Drawable objects
 #[derive(Debug, Clone)]
@@ -15,6 +27,12 @@ pub struct GameControls {
     pub title: String,
     pub button_height: f32,
     pub buttons: Vec<Button>,
+    pub player_list: PlayerList,
+    pub mining: MiningStatus,
+    pub board_info: BoardInfo,
+    pub heatmap: HeatmapLegend,
+    pointer: Option<(f64, f64)>, // Last mouse position, for highlighting the button under it
+    pressed: Option<Action>,     // The button the mouse went down on
 }
 
 impl GameControls {
@@ -28,6 +46,12 @@ impl GameControls {
             title: String::from("Game Controls"),
             button_height: 0.0,
             buttons: Vec::new(),
+            player_list: PlayerList::new(),
+            mining: MiningStatus::new(),
+            board_info: BoardInfo::new(),
+            heatmap: HeatmapLegend::new(),
+            pointer: None,
+            pressed: None,
         }
     }
 
@@ -44,14 +68,21 @@ impl GameControls {
     ) {
         self.x = x;
         self.y = y;
-        self.width = width;
-        self.height = height;
+        // The board can leave the panel no room at all
+        self.width = width.max(0.0);
+        self.height = height.max(0.0);
         self.bg_color = bg_color;
         self.title = title;
         self.button_height = button_height;
         self.buttons = buttons;
     }
 
+    pub fn set_players(&mut self, users: &[User], current: usize, winner: Option<usize>) {
+        self.player_list.users = users.to_vec();
+        self.player_list.current = current;
+        self.player_list.winner = winner;
+    }
+
     pub fn add_button(&mut self, button: Button) {
         self.buttons.push(button);
     }
@@ -63,19 +94,73 @@ impl GameControls {
         px >= self.x && px < self.x + self.width && py >= self.y && py < self.y + self.height
     }
 
-    pub fn onclick(&self, x: f64, y: f64) -> Option<String> {
-        for button in &self.buttons {
-            //if px >= self.x && px < self.x + self.width && py >= self.y && py < self.y + self.height
-            if button.contains_point(x, y) {
-                return Some(button.label.clone());
+    // The enabled button at the point, disabled buttons ignore the mouse
+    fn button_at(&self, x: f64, y: f64) -> Option<&Button> {
+        self.buttons
+            .iter()
+            .find(|button| button.enabled() && button.contains_point(x, y))
+    }
+
+    // Returns true when the highlighted button changed and the panel needs drawing again
+    pub fn pointer_moved(&mut self, x: f64, y: f64) -> bool {
+        let before = self.button_at_pointer();
+        self.pointer = Some((x, y));
+        self.button_at_pointer() != before
+    }
+
+    pub fn pointer_left(&mut self) -> bool {
+        let before = self.button_at_pointer();
+        self.pointer = None;
+        self.pressed = None;
+        before.is_some()
+    }
+
+    fn button_at_pointer(&self) -> Option<Action> {
+        let (x, y) = self.pointer?;
+        self.button_at(x, y).map(|button| button.action)
+    }
+
+    // The mouse went down, returns true if it was on a button
+    pub fn press(&mut self, x: f64, y: f64) -> bool {
+        self.pointer = Some((x, y));
+        self.pressed = self.button_at(x, y).map(|button| button.action);
+        self.pressed.is_some()
+    }
+
+    // The mouse came up, a button is clicked when it goes down and up on it
+    pub fn release(&mut self, x: f64, y: f64) -> Option<Action> {
+        self.pointer = Some((x, y));
+        let pressed = self.pressed.take()?;
+        self.button_at(x, y)
+            .map(|button| button.action)
+            .filter(|action| *action == pressed)
+    }
+
+    // How a button looks for where the mouse is
+    fn pointer_state(&self, button: &Button) -> ButtonState {
+        if !button.enabled() {
+            return ButtonState::Disabled;
+        }
+        match self.pointer {
+            Some((x, y)) if button.contains_point(x, y) => {
+                if self.pressed == Some(button.action) {
+                    ButtonState::Pressed
+                } else {
+                    ButtonState::Hovered
+                }
             }
+            _ => ButtonState::Normal,
         }
-        None
     }
 }
 
 impl GameControls {
     pub fn draw(&mut self, pixmap: &mut Pixmap) {
+        // No room beside the board, as in a window taller than it is wide
+        if self.width <= 0.0 || self.height <= 0.0 {
+            return;
+        }
+
         // The font for the numbering of the squares
         //let font_data = include_bytes!("./DejaVuSans-Bold.ttf");
         let font_data = font_list!();
@@ -125,13 +210,51 @@ impl GameControls {
             None,
         );
 
-        // Render the square label using ab_glyph and tiny_skia
-        let text = &self.title;
-        let text_size = self.button_height * 0.8; // Adjust text size relative to square size
+        // Title, buttons, then the status panels and the player list, squeezed to fit
+        // the panel when it is short and with taller buttons when there is room
+        let button_height = self.button_height;
+        let section_gap = Item::shrinkable(button_height * 0.3, 0.0);
+        let rows = |count: usize, height: f32| {
+            let count = count as f32;
+            Item::shrinkable(height * count, height * 0.6 * count)
+        };
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_padding(10.0);
+        stack.set_spacing(button_height * 0.2);
+        stack.set_align(Align::Stretch);
 
-        // Calculate text position (center it in the square)
-        let text_x = self.x + text_size * 0.2;
-        let text_y = self.y + text_size;
+        let title = stack.add(Item::shrinkable(button_height * 1.4, button_height));
+        let buttons: Vec<usize> = self
+            .buttons
+            .iter()
+            .map(|_| {
+                stack.add(Item {
+                    grow: 1.0,
+                    max: button_height * 1.25,
+                    ..Item::shrinkable(button_height, button_height * 0.6)
+                })
+            })
+            .collect();
+        stack.add(section_gap);
+        let mining = stack.add(rows(self.mining.rows(), button_height * 0.8));
+        stack.add(section_gap);
+        let board_info = stack.add(rows(self.board_info.rows(), button_height * 0.8));
+        let heatmap = if self.heatmap.rows() > 0 {
+            stack.add(section_gap);
+            Some(stack.add(rows(self.heatmap.rows(), button_height * 0.8)))
+        } else {
+            None
+        };
+        stack.add(section_gap);
+        let players = stack.add(rows(self.player_list.users.len(), button_height * 0.9));
+
+        let placed = stack.layout(Bounds::new(self.x, self.y, self.width, self.height));
+
+        // Render the title using ab_glyph and tiny_skia
+        let text = &self.title;
+        let text_size = placed[title].height * 0.6;
+        let text_x = placed[title].x;
+        let text_y = placed[title].y + placed[title].height * 0.7;
 
         // Create a path for the text
         let mut text_path = PathBuilder::new();
@@ -180,34 +303,79 @@ impl GameControls {
         }
 
         // Draw each button
-        //for button in &mut self.buttons {
-        for indx in 0..self.buttons.len() {
-            let y_offset = (self.button_height + (self.button_height * 0.2)) * indx as f32
-                + (2.0 * self.button_height);
-
-            self.buttons[indx].set_start(self.x + 10.0, self.y + y_offset);
-            self.buttons[indx].set_end(
-                self.x + self.width - 10.0,
-                self.y + y_offset + self.button_height,
-            );
+        for (indx, placed_at) in buttons.into_iter().enumerate() {
+            self.buttons[indx].set_bounds(placed[placed_at]);
+            self.buttons[indx].state = self.pointer_state(&self.buttons[indx]);
             // Draw the button
             self.buttons[indx].draw(pixmap);
         }
+
+        // Panels made of rows get their row height from the room they were given
+        let row_height = |bounds: Bounds, rows: usize| bounds.height / rows.max(1) as f32;
+        let bounds = placed[mining];
+        self.mining.set_bounds(
+            bounds.x,
+            bounds.y,
+            bounds.width,
+            row_height(bounds, self.mining.rows()),
+        );
+        self.mining.draw(pixmap);
+
+        let bounds = placed[board_info];
+        self.board_info.set_bounds(
+            bounds.x,
+            bounds.y,
+            bounds.width,
+            row_height(bounds, self.board_info.rows()),
+        );
+        self.board_info.draw(pixmap);
+
+        if let Some(heatmap) = heatmap {
+            let bounds = placed[heatmap];
+            self.heatmap.set_bounds(
+                bounds.x,
+                bounds.y,
+                bounds.width,
+                row_height(bounds, self.heatmap.rows()),
+            );
+            self.heatmap.draw(pixmap);
+        }
+
+        let bounds = placed[players];
+        self.player_list.set_bounds(
+            bounds.x,
+            bounds.y,
+            bounds.width,
+            row_height(bounds, self.player_list.users.len()),
+        );
+        self.player_list.draw(pixmap);
     }
 }
 
+// A button says what it does with an action, the label is only shown
 #[derive(Debug, Clone)]
-pub struct Button {
+pub struct Button<A = Action> {
     pub start_x: f32,
     pub start_y: f32,
     pub end_x: f32,
     pub end_y: f32,
     pub label: String,
     pub color: u32,
+    pub action: A,
+    pub hint: Option<String>, // Keyboard shortcut shown in the corner
+    pub state: ButtonState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonState {
+    Normal,
+    Hovered,
+    Pressed,  // The mouse went down on it and is still over it
+    Disabled, // Greyed out and ignores the mouse
 }
 
-impl Button {
-    pub fn new(label: String, color: u32) -> Self {
+impl<A> Button<A> {
+    pub fn new(label: String, color: u32, action: A) -> Self {
         Button {
             start_x: 0.0,
             start_y: 0.0,
@@ -215,9 +383,28 @@ impl Button {
             end_y: 0.0,
             label,
             color,
+            action,
+            hint: None,
+            state: ButtonState::Normal,
         }
     }
 
+    pub fn set_hint(&mut self, hint: Option<String>) {
+        self.hint = hint;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.state = if enabled {
+            ButtonState::Normal
+        } else {
+            ButtonState::Disabled
+        };
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.state != ButtonState::Disabled
+    }
+
     pub fn set_start(&mut self, x: f32, y: f32) {
         self.start_x = x;
         self.start_y = y;
@@ -228,6 +415,11 @@ impl Button {
         self.end_y = y;
     }
 
+    pub fn set_bounds(&mut self, bounds: Bounds) {
+        self.set_start(bounds.x, bounds.y);
+        self.set_end(bounds.x + bounds.width, bounds.bottom());
+    }
+
     pub fn contains_point(&self, px: f64, py: f64) -> bool {
         let px = px as f32;
         let py = py as f32;
@@ -236,18 +428,39 @@ impl Button {
     }
 }
 
-impl Drawable for Button {
+impl<A> Drawable for Button<A> {
     fn draw(&self, pixmap: &mut Pixmap) {
         let path = PathBuilder::from_rect(
             Rect::from_ltrb(self.start_x, self.start_y, self.end_x, self.end_y).unwrap(),
         );
         let thickness = 4.0;
 
+        // Disabled buttons are grey, the one under the mouse is filled in lightly
+        // and darker while it is held down
+        let color = match self.state {
+            ButtonState::Disabled => 0xAAAAAAFF,
+            _ => self.color,
+        };
+        let fill = match self.state {
+            ButtonState::Hovered => Some(0x30),
+            ButtonState::Pressed => Some(0x70),
+            _ => None,
+        };
+        if let Some(alpha) = fill {
+            pixmap.fill_path(
+                &path,
+                &paint_for((color & 0xFFFFFF00) | alpha),
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+        }
+
         // Extract RGBA components from u32 color (format: 0xRRGGBBAA)
-        let r = ((self.color >> 24) & 0xFF) as f32 / 255.0;
-        let g = ((self.color >> 16) & 0xFF) as f32 / 255.0;
-        let b = ((self.color >> 8) & 0xFF) as f32 / 255.0;
-        let a = (self.color & 0xFF) as f32 / 255.0;
+        let r = ((color >> 24) & 0xFF) as f32 / 255.0;
+        let g = ((color >> 16) & 0xFF) as f32 / 255.0;
+        let b = ((color >> 8) & 0xFF) as f32 / 255.0;
+        let a = (color & 0xFF) as f32 / 255.0;
 
         // Set up paint for the arrow
         let mut paint = Paint::default();
@@ -278,7 +491,10 @@ impl Drawable for Button {
         // Calculate text position (center it in the square)
         let text_x = self.start_x + ((self.end_x - self.start_x) / 2.0)
             - ((self.label.len() / 2) as f32 * (text_size));
-        let text_y = self.start_y + (self.end_y - self.start_y) * 0.1 + text_size * 0.8;
+        let mut text_y = self.start_y + (self.end_y - self.start_y) * 0.1 + text_size * 0.8;
+        if self.state == ButtonState::Pressed {
+            text_y += 1.0; // The label sinks a little while held down
+        }
         //let text_y = self.y + self.size * 0.9;
 
         // Create a path for the text
@@ -321,5 +537,1056 @@ impl Drawable for Button {
                 None,
             );
         }
+
+        // The shortcut goes small in the bottom right corner, in a lighter shade
+        if let Some(hint) = &self.hint {
+            let hint_size = (self.end_y - self.start_y) * 0.4;
+            let hint_x = self.end_x - text_width(hint, hint_size) - thickness * 2.0;
+            let hint_y = self.end_y - thickness * 2.0;
+            draw_text(
+                pixmap,
+                hint,
+                hint_x,
+                hint_y,
+                hint_size,
+                (color & 0xFFFFFF00) | 0xC0,
+            );
+        }
+    }
+}
+
+// Paint for a colour in 0xRRGGBBAA format
+fn paint_for(color: u32) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(
+        (color >> 24) as u8,
+        (color >> 16) as u8,
+        (color >> 8) as u8,
+        color as u8,
+    );
+    paint.anti_alias = true;
+    paint
+}
+
+fn fill_rect(pixmap: &mut Pixmap, x: f32, y: f32, width: f32, height: f32, color: u32) {
+    if let Some(rect) = Rect::from_xywh(x, y, width, height) {
+        pixmap.fill_rect(rect, &paint_for(color), Transform::identity(), None);
+    }
+}
+
+fn stroke_rect(pixmap: &mut Pixmap, x: f32, y: f32, width: f32, height: f32, color: u32) {
+    if let Some(rect) = Rect::from_xywh(x, y, width, height) {
+        pixmap.stroke_path(
+            &PathBuilder::from_rect(rect),
+            &paint_for(color),
+            &Stroke {
+                width: 2.0,
+                ..Default::default()
+            },
+            Transform::identity(),
+            None,
+        );
+    }
+}
+
+// Draws a line of text with its baseline at y
+pub fn draw_text(pixmap: &mut Pixmap, text: &str, x: f32, y: f32, size: f32, color: u32) {
+    let font_data = font_list!();
+    let font = FontArc::try_from_slice(font_data[0]).expect("Failed to load font");
+
+    let mut text_path = PathBuilder::new();
+    push_text(&mut text_path, &font, text, x, y, size);
+    if let Some(text_path) = text_path.finish() {
+        pixmap.fill_path(
+            &text_path,
+            &paint_for(color),
+            FillRule::Winding,
+            Transform::identity(),
+            None,
+        );
+    }
+}
+
+pub fn text_width(text: &str, size: f32) -> f32 {
+    let font_data = font_list!();
+    let font = FontArc::try_from_slice(font_data[0]).expect("Failed to load font");
+
+    text.chars()
+        .map(|c| font.h_advance_unscaled(font.glyph_id(c)) * size / font.units_per_em().unwrap())
+        .sum()
+}
+
+fn point_in(px: f64, py: f64, x: f32, y: f32, width: f32, height: f32) -> bool {
+    let px = px as f32;
+    let py = py as f32;
+
+    px >= x && px < x + width && py >= y && py < y + height
+}
+
+// The settings widgets share one row layout: label in the left half, control in the right half
+fn draw_row_label(pixmap: &mut Pixmap, label: &str, x: f32, y: f32, height: f32) {
+    draw_text(
+        pixmap,
+        label,
+        x,
+        y + height * 0.75,
+        height * 0.55,
+        0x000000FF,
+    );
+}
+
+// On/off switch
+#[derive(Debug, Clone)]
+pub struct Toggle {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: String,
+    pub value: bool,
+}
+
+impl Toggle {
+    pub fn new(label: String, value: bool) -> Self {
+        Toggle {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            label,
+            value,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    // Flips the value when the switch is clicked, returns true if it changed
+    pub fn onclick(&mut self, px: f64, py: f64) -> bool {
+        let switch_x = self.x + self.width * 0.5;
+        if point_in(px, py, switch_x, self.y, self.height * 2.0, self.height) {
+            self.value = !self.value;
+            return true;
+        }
+        false
+    }
+}
+
+impl Drawable for Toggle {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        draw_row_label(pixmap, &self.label, self.x, self.y, self.height);
+
+        let switch_x = self.x + self.width * 0.5;
+        let track_color = if self.value { 0x00AA00FF } else { 0xAAAAAAFF };
+        fill_rect(
+            pixmap,
+            switch_x,
+            self.y + self.height * 0.2,
+            self.height * 2.0,
+            self.height * 0.6,
+            track_color,
+        );
+
+        // The knob sits on the right when the switch is on
+        let knob_radius = self.height * 0.4;
+        let knob_x = if self.value {
+            switch_x + self.height * 2.0 - knob_radius
+        } else {
+            switch_x + knob_radius
+        };
+        if let Some(knob) =
+            PathBuilder::from_circle(knob_x, self.y + self.height * 0.5, knob_radius)
+        {
+            pixmap.fill_path(
+                &knob,
+                &paint_for(0xFFFFFFFF),
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+            pixmap.stroke_path(
+                &knob,
+                &paint_for(0x000000C8),
+                &Stroke::default(),
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+}
+
+// Box showing the selected option, clicking it opens the list of options below it
+#[derive(Debug, Clone)]
+pub struct Dropdown {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: String,
+    pub options: Vec<String>,
+    pub selected: usize,
+    pub open: bool,
+}
+
+impl Dropdown {
+    pub fn new(label: String, options: Vec<String>, selected: usize) -> Self {
+        Dropdown {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            label,
+            options,
+            selected,
+            open: false,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    // Opens the list or picks an option, returns true if the click was used
+    pub fn onclick(&mut self, px: f64, py: f64) -> bool {
+        let box_x = self.x + self.width * 0.5;
+        let box_width = self.width * 0.5;
+
+        if self.open {
+            self.open = false;
+            for indx in 0..self.options.len() {
+                let option_y = self.y + self.height * (indx + 1) as f32;
+                if point_in(px, py, box_x, option_y, box_width, self.height) {
+                    self.selected = indx;
+                    return true;
+                }
+            }
+            return point_in(px, py, box_x, self.y, box_width, self.height);
+        }
+
+        if point_in(px, py, box_x, self.y, box_width, self.height) {
+            self.open = true;
+            return true;
+        }
+        false
+    }
+
+    pub fn selected_label(&self) -> &str {
+        self.options
+            .get(self.selected)
+            .map(|option| option.as_str())
+            .unwrap_or("")
+    }
+}
+
+impl Drawable for Dropdown {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        draw_row_label(pixmap, &self.label, self.x, self.y, self.height);
+
+        let box_x = self.x + self.width * 0.5;
+        let box_width = self.width * 0.5;
+        let text_size = self.height * 0.55;
+
+        fill_rect(pixmap, box_x, self.y, box_width, self.height, 0xFFFFFFFF);
+        stroke_rect(pixmap, box_x, self.y, box_width, self.height, 0x000000C8);
+        draw_text(
+            pixmap,
+            self.selected_label(),
+            box_x + text_size * 0.3,
+            self.y + self.height * 0.75,
+            text_size,
+            0x000000FF,
+        );
+        draw_text(
+            pixmap,
+            "▼",
+            box_x + box_width - text_size * 1.2,
+            self.y + self.height * 0.75,
+            text_size,
+            0x000000FF,
+        );
+
+        if !self.open {
+            return;
+        }
+
+        // Options list, drawn over whatever is below the dropdown
+        for (indx, option) in self.options.iter().enumerate() {
+            let option_y = self.y + self.height * (indx + 1) as f32;
+            let color = if indx == self.selected {
+                0xCCDDFFFF
+            } else {
+                0xFFFFFFFF
+            };
+            fill_rect(pixmap, box_x, option_y, box_width, self.height, color);
+            stroke_rect(pixmap, box_x, option_y, box_width, self.height, 0x000000C8);
+            draw_text(
+                pixmap,
+                option,
+                box_x + text_size * 0.3,
+                option_y + self.height * 0.75,
+                text_size,
+                0x000000FF,
+            );
+        }
+    }
+}
+
+// Numeric value with - and + buttons
+#[derive(Debug, Clone)]
+pub struct Stepper {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: String,
+    pub value: u32,
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Stepper {
+    pub fn new(label: String, value: u32, min: u32, max: u32) -> Self {
+        Stepper {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            label,
+            value: value.clamp(min, max),
+            min,
+            max,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    // Steps the value when - or + is clicked, returns true if it changed
+    pub fn onclick(&mut self, px: f64, py: f64) -> bool {
+        let minus_x = self.x + self.width * 0.5;
+        let plus_x = self.x + self.width - self.height;
+
+        if point_in(px, py, minus_x, self.y, self.height, self.height) && self.value > self.min {
+            self.value -= 1;
+            return true;
+        }
+        if point_in(px, py, plus_x, self.y, self.height, self.height) && self.value < self.max {
+            self.value += 1;
+            return true;
+        }
+        false
+    }
+}
+
+impl Drawable for Stepper {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        draw_row_label(pixmap, &self.label, self.x, self.y, self.height);
+
+        let minus_x = self.x + self.width * 0.5;
+        let plus_x = self.x + self.width - self.height;
+        let text_size = self.height * 0.55;
+
+        for (button_x, sign, enabled) in [
+            (minus_x, "-", self.value > self.min),
+            (plus_x, "+", self.value < self.max),
+        ] {
+            let color = if enabled { 0x000000FF } else { 0x00000050 };
+            stroke_rect(pixmap, button_x, self.y, self.height, self.height, color);
+            draw_text(
+                pixmap,
+                sign,
+                button_x + (self.height - text_width(sign, text_size)) / 2.0,
+                self.y + self.height * 0.75,
+                text_size,
+                color,
+            );
+        }
+
+        // Value centred between the two buttons
+        let value = self.value.to_string();
+        let middle = (minus_x + self.height + plus_x) / 2.0;
+        draw_text(
+            pixmap,
+            &value,
+            middle - text_width(&value, text_size) / 2.0,
+            self.y + self.height * 0.75,
+            text_size,
+            0x000000FF,
+        );
+    }
+}
+
+// Single line of editable text, receives typed characters while focused
+#[derive(Debug, Clone)]
+pub struct TextField {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: String,
+    pub text: String,
+    pub focused: bool,
+    pub max_len: usize,
+}
+
+impl TextField {
+    pub fn new(label: String, text: String, max_len: usize) -> Self {
+        TextField {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            label,
+            text,
+            focused: false,
+            max_len,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    // Focuses the field when its box is clicked and unfocuses it otherwise
+    pub fn onclick(&mut self, px: f64, py: f64) -> bool {
+        self.focused = point_in(
+            px,
+            py,
+            self.x + self.width * 0.5,
+            self.y,
+            self.width * 0.5,
+            self.height,
+        );
+        self.focused
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            if self.text.chars().count() >= self.max_len {
+                break;
+            }
+            self.text.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+}
+
+impl Drawable for TextField {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        draw_row_label(pixmap, &self.label, self.x, self.y, self.height);
+
+        let box_x = self.x + self.width * 0.5;
+        let box_width = self.width * 0.5;
+        let text_size = self.height * 0.55;
+        let border = if self.focused { 0x0044CCFF } else { 0x000000C8 };
+
+        fill_rect(pixmap, box_x, self.y, box_width, self.height, 0xFFFFFFFF);
+        stroke_rect(pixmap, box_x, self.y, box_width, self.height, border);
+
+        // Show a caret at the end of the text while typing
+        let text = if self.focused {
+            format!("{}|", self.text)
+        } else {
+            self.text.clone()
+        };
+        draw_text(
+            pixmap,
+            &text,
+            box_x + text_size * 0.3,
+            self.y + self.height * 0.75,
+            text_size,
+            0x000000FF,
+        );
+    }
+}
+
+// Row of colour swatches with one selected
+#[derive(Debug, Clone)]
+pub struct ColorPicker {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub label: String,
+    pub colors: Vec<u32>,
+    pub selected: usize,
+}
+
+impl ColorPicker {
+    pub fn new(label: String, colors: Vec<u32>, selected: usize) -> Self {
+        ColorPicker {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            label,
+            colors,
+            selected,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    fn swatch_x(&self, indx: usize) -> f32 {
+        self.x + self.width * 0.5 + indx as f32 * self.height * 1.2
+    }
+
+    // Selects the clicked swatch, returns true if one was hit
+    pub fn onclick(&mut self, px: f64, py: f64) -> bool {
+        for indx in 0..self.colors.len() {
+            if point_in(
+                px,
+                py,
+                self.swatch_x(indx),
+                self.y,
+                self.height,
+                self.height,
+            ) {
+                self.selected = indx;
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn selected_color(&self) -> u32 {
+        self.colors[self.selected]
+    }
+}
+
+impl Drawable for ColorPicker {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        draw_row_label(pixmap, &self.label, self.x, self.y, self.height);
+
+        for (indx, color) in self.colors.iter().enumerate() {
+            let swatch_x = self.swatch_x(indx);
+            fill_rect(pixmap, swatch_x, self.y, self.height, self.height, *color);
+            if indx == self.selected {
+                // Double outline marks the selected colour
+                stroke_rect(
+                    pixmap,
+                    swatch_x,
+                    self.y,
+                    self.height,
+                    self.height,
+                    0x000000FF,
+                );
+                stroke_rect(
+                    pixmap,
+                    swatch_x + 3.0,
+                    self.y + 3.0,
+                    self.height - 6.0,
+                    self.height - 6.0,
+                    0xFFFFFFFF,
+                );
+            }
+        }
+    }
+}
+
+// Players in seat order with their square, the current player is highlighted
+#[derive(Debug, Clone)]
+pub struct PlayerList {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+    pub users: Vec<User>,
+    pub current: usize,
+    pub winner: Option<usize>,
+}
+
+impl PlayerList {
+    pub fn new() -> Self {
+        PlayerList {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            row_height: 0.0,
+            users: Vec::new(),
+            current: 0,
+            winner: None,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, row_height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.row_height = row_height;
+    }
+}
+
+impl Default for PlayerList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for PlayerList {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        let text_size = self.row_height * 0.55;
+        let baseline = self.row_height * 0.7;
+
+        for (indx, user) in self.users.iter().enumerate() {
+            let row_y = self.y + indx as f32 * self.row_height;
+
+            // Once the game is won the winner is highlighted instead of the current player
+            let highlighted = match self.winner {
+                Some(winner) => winner == indx,
+                None => self.current == indx,
+            };
+            if highlighted {
+                fill_rect(
+                    pixmap,
+                    self.x,
+                    row_y,
+                    self.width,
+                    self.row_height,
+                    0xFFEE88FF,
+                );
+            }
+
+            let swatch = self.row_height * 0.6;
+            fill_rect(
+                pixmap,
+                self.x + swatch * 0.3,
+                row_y + (self.row_height - swatch) / 2.0,
+                swatch,
+                swatch,
+                user.color,
+            );
+
+            let mut name = if self.winner == Some(indx) {
+                format!("★ {}", user.name)
+            } else {
+                user.name.clone()
+            };
+            if user.controller.is_ai() {
+                name.push_str(" (CPU)");
+            }
+            draw_text(
+                pixmap,
+                &name,
+                self.x + swatch * 1.6,
+                row_y + baseline,
+                text_size,
+                0x000000FF,
+            );
+
+            // Rewards from digging sit next to the square number
+            let mut square = user.position.to_string();
+            if user.shields > 0 {
+                square = format!("◆{}  {}", user.shields, square);
+            }
+            if user.rerolls > 0 {
+                square = format!("↻{}  {}", user.rerolls, square);
+            }
+            draw_text(
+                pixmap,
+                &square,
+                self.x + self.width - text_width(&square, text_size) - swatch * 0.3,
+                row_y + baseline,
+                text_size,
+                0x000000FF,
+            );
+        }
+    }
+}
+
+// State of the Dig search: whether it runs, how far it got and what it found
+#[derive(Debug, Clone)]
+pub struct MiningStatus {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+    pub running: bool,
+    pub iterations: u64, // Squarings done since the search started
+    pub found: u32,
+    pub last_found: Option<String>,
+    started: Option<Instant>,
+    resumed_at: u64, // Squarings taken over from a checkpoint, left out of the speed
+}
+
+impl MiningStatus {
+    pub fn new() -> Self {
+        MiningStatus {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            row_height: 0.0,
+            running: false,
+            iterations: 0,
+            found: 0,
+            last_found: None,
+            started: None,
+            resumed_at: 0,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, row_height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.row_height = row_height;
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+        self.started = Some(Instant::now());
+        self.iterations = 0;
+        self.resumed_at = 0;
+        self.found = 0;
+        self.last_found = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.started = None;
+    }
+
+    pub fn update(&mut self, event: &SearchEvent) {
+        match event {
+            SearchEvent::Progress(iterations) => self.iterations = *iterations,
+            SearchEvent::Resumed(iterations) => {
+                self.iterations = *iterations;
+                self.resumed_at = *iterations;
+            }
+            SearchEvent::Found(proof) => {
+                self.found += 1;
+                self.last_found = Some(proof.y.a.to_string().chars().take(8).collect());
+            }
+            SearchEvent::Missed(_) => {}
+        }
+    }
+
+    pub fn iterations_per_second(&self) -> f64 {
+        match self.started {
+            Some(started) => {
+                let seconds = started.elapsed().as_secs_f64();
+                if seconds > 0.0 {
+                    self.iterations.saturating_sub(self.resumed_at) as f64 / seconds
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    // Rows the status takes up when drawn
+    pub fn rows(&self) -> usize {
+        3
+    }
+}
+
+impl Default for MiningStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for MiningStatus {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        let text_size = self.row_height * 0.55;
+        let baseline = self.row_height * 0.7;
+        let dot = self.row_height * 0.4;
+
+        // Green light while digging, grey when idle
+        let (light, state) = if self.running {
+            (0x00AA00FF, "Digging")
+        } else {
+            (0x999999FF, "Not digging")
+        };
+        fill_rect(
+            pixmap,
+            self.x + dot * 0.5,
+            self.y + (self.row_height - dot) / 2.0,
+            dot,
+            dot,
+            light,
+        );
+
+        let rate = if self.running {
+            format!(
+                "{} squarings, {:.0}/s",
+                self.iterations,
+                self.iterations_per_second()
+            )
+        } else {
+            format!("{} squarings", self.iterations)
+        };
+        let found = match &self.last_found {
+            Some(last) => format!("Found {}, last {}…", self.found, last),
+            None => format!("Found {}", self.found),
+        };
+
+        for (indx, line) in [state.to_string(), rate, found].iter().enumerate() {
+            draw_text(
+                pixmap,
+                line,
+                self.x + dot * 2.0,
+                self.y + indx as f32 * self.row_height + baseline,
+                text_size,
+                0x000000FF,
+            );
+        }
+    }
+}
+
+// Expected game length and busiest squares of the board in play, see analysis.rs
+#[derive(Debug, Clone)]
+pub struct BoardInfo {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+    pub analysis: Option<BoardAnalysis>,
+    board: Option<BoardKey>, // The board the analysis is for
+}
+
+// What the analysis depends on, it is only worked out again when this changes
+type BoardKey = (u32, RuleSet, Vec<(u32, u32)>, Vec<(u32, SquareEffect)>);
+
+impl BoardInfo {
+    pub fn new() -> Self {
+        BoardInfo {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            row_height: 0.0,
+            analysis: None,
+            board: None,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, row_height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.row_height = row_height;
+    }
+
+    pub fn update(&mut self, state: &GameState) {
+        let board = (
+            state.grid_size,
+            state.settings.rules,
+            state.arrows.clone(),
+            state.special_squares.clone(),
+        );
+        if self.board.as_ref() != Some(&board) {
+            self.analysis = Some(analysis::analyze(state));
+            self.board = Some(board);
+        }
+    }
+
+    // Rows the panel takes up when drawn, the last one holds the finish curve
+    pub fn rows(&self) -> usize {
+        4
+    }
+}
+
+impl Default for BoardInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for BoardInfo {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        let Some(analysis) = &self.analysis else {
+            return;
+        };
+        let text_size = self.row_height * 0.55;
+        let baseline = self.row_height * 0.7;
+
+        let turns = |probability| {
+            analysis
+                .turns_for(probability)
+                .map_or("-".to_string(), |turns| turns.to_string())
+        };
+        let busiest: Vec<String> = analysis
+            .most_visited(3)
+            .iter()
+            .map(|(square, _)| square.to_string())
+            .collect();
+        let lines = [
+            format!("Board: {:.1} turns", analysis.expected_turns),
+            format!("Half by {}, 90% by {}", turns(0.5), turns(0.9)),
+            format!("Busiest {}", busiest.join(", ")),
+        ];
+        for (indx, line) in lines.iter().enumerate() {
+            draw_text(
+                pixmap,
+                line,
+                self.x,
+                self.y + indx as f32 * self.row_height + baseline,
+                text_size,
+                0x000000FF,
+            );
+        }
+
+        // Chance of having finished by each turn, up to the turn nearly all games are done
+        let top = self.y + lines.len() as f32 * self.row_height + self.row_height * 0.1;
+        let height = self.row_height * 0.8;
+        stroke_rect(pixmap, self.x, top, self.width, height, 0xCCCCCCFF);
+        let shown = analysis
+            .turns_for(0.99)
+            .unwrap_or(analysis.finish_curve.len());
+        let mut curve = PathBuilder::new();
+        for (turn, finished) in analysis.finish_curve.iter().take(shown).enumerate() {
+            let x = self.x + self.width * (turn + 1) as f32 / shown as f32;
+            let y = top + height * (1.0 - *finished as f32);
+            if turn == 0 {
+                curve.move_to(self.x, top + height);
+            }
+            curve.line_to(x, y);
+        }
+        if let Some(curve) = curve.finish() {
+            pixmap.stroke_path(
+                &curve,
+                &paint_for(0x0066CCFF),
+                &Stroke {
+                    width: 1.5,
+                    ..Default::default()
+                },
+                Transform::identity(),
+                None,
+            );
+        }
+    }
+}
+
+// Key for the heatmap overlay: where the counts come from and what full colour stands for
+#[derive(Debug, Clone)]
+pub struct HeatmapLegend {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+    pub source: HeatSource,
+    pub busiest: f64, // Count of the busiest square
+}
+
+impl HeatmapLegend {
+    pub fn new() -> Self {
+        HeatmapLegend {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            row_height: 0.0,
+            source: HeatSource::Off,
+            busiest: 0.0,
+        }
+    }
+
+    pub fn set_bounds(&mut self, x: f32, y: f32, width: f32, row_height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.row_height = row_height;
+    }
+
+    // Rows the legend takes up when drawn, none while the overlay is off
+    pub fn rows(&self) -> usize {
+        match self.source {
+            HeatSource::Off => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl Default for HeatmapLegend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drawable for HeatmapLegend {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        if self.source == HeatSource::Off {
+            return;
+        }
+        let text_size = self.row_height * 0.55;
+        let baseline = self.row_height * 0.7;
+        let gap = self.row_height * 0.3;
+
+        // Colour scale from nothing to the busiest square, with the counts at the ends
+        let start = format!("{} 0", self.source);
+        let busiest = if self.source == HeatSource::Expected {
+            format!("{:.2}", self.busiest)
+        } else {
+            format!("{:.0}", self.busiest)
+        };
+        let start_width = text_width(&start, text_size) + gap;
+        let bar_x = self.x + start_width;
+        let bar_width = (self.width - start_width - text_width(&busiest, text_size) - gap).max(0.0);
+        let top = self.y + self.row_height * 0.2;
+        let height = self.row_height * 0.6;
+        let stops = (0..=4)
+            .map(|step| {
+                let color = heat_color((step as f64 / 4.0).max(0.01));
+                GradientStop::new(
+                    step as f32 / 4.0,
+                    Color::from_rgba8(
+                        (color >> 24) as u8,
+                        (color >> 16) as u8,
+                        (color >> 8) as u8,
+                        color as u8,
+                    ),
+                )
+            })
+            .collect();
+        if let (Some(shader), Some(rect)) = (
+            LinearGradient::new(
+                Point::from_xy(bar_x, top),
+                Point::from_xy(bar_x + bar_width, top),
+                stops,
+                SpreadMode::Pad,
+                Transform::identity(),
+            ),
+            Rect::from_xywh(bar_x, top, bar_width, height),
+        ) {
+            let paint = Paint {
+                shader,
+                ..Default::default()
+            };
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+        stroke_rect(pixmap, bar_x, top, bar_width, height, 0xCCCCCCFF);
+
+        let text_y = self.y + baseline;
+        draw_text(pixmap, &start, self.x, text_y, text_size, 0x000000FF);
+        draw_text(
+            pixmap,
+            &busiest,
+            bar_x + bar_width + gap,
+            text_y,
+            text_size,
+            0x000000FF,
+        );
     }
 }
@@ -1,34 +1,124 @@
 // File: src/game_data.rs
 //use crate::game_board::GameBoard::SquareBoard;
-use crate::objects::{GameSettings, User};
+use crate::ai;
+use crate::analysis;
+use crate::fair_dice::{FairDice, Seed};
+use crate::objects::{
+    BoardSeed, Controller, Difficulty, GameSettings, PlayerProfile, Reward, SquareEffect, User,
+};
+use crate::vdf::{self, DigLedger, VdfProof};
 //use bincode;
-use curv::arithmetic::Converter;
 use curv::BigInt;
-use rand::Rng;
+use curv::arithmetic::{BitManipulation, Converter};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 //use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 
+// Digits in a full seed, special squares are read from digit 48 on
+const SEED_DIGITS: usize = 192;
+// Boards tried for a fair seed before settling for the closest
+const FAIR_SEED_TRIES: usize = 200;
+// Board sizes, in squares along a side, and die sizes a game can be set up with
+const MIN_GRID_SIZE: u32 = 4;
+const MAX_GRID_SIZE: u32 = 20;
+pub const MAX_DIE_SIDES: u32 = 20;
+
 // Main game data container
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GameState {
     // Drawable objects stored as trait objects
     //pub drawable_objects: Vec<Box<dyn Drawable>>,
     pub arrows: Vec<(u32, u32)>, // Pairs of arrows
+    #[serde(default)]
+    pub special_squares: Vec<(u32, SquareEffect)>, // Square number and its effect
 
     // Non-drawable objects
-    pub users: [User; 2],
+    pub users: Vec<User>,
     pub settings: GameSettings,
     pub colors: Vec<u32>,
     //pub board_type: GameBoard,
     pub grid_size: u32,
     //pub user_position: u32,
     pub new_game: bool,
+    #[serde(default)]
+    pub current_player: usize, // Index into users of the player whose turn it is
+    #[serde(default)]
+    pub winner: Option<usize>,
+    #[serde(default)]
+    pub pending_dice: Vec<u32>, // Dice waiting for a human player to pick one
+    #[serde(default)]
+    pub turn_dice: Option<FairDice>, // Agreed dice for the turn in play, see take_turn_with_seed
+    #[serde(default)]
+    pub extra_die: bool, // A re-roll token was spent on the turn in play
+    #[serde(default)]
+    pub turn: u32, // Turns finished this game, skipped ones included
+    #[serde(default)]
+    pub dig_ledger: DigLedger, // Dig rounds handed in, kept across games like the rewards
+    #[serde(skip)]
+    pub history: Vec<GameEvent>, // What happened so far this game
+    #[serde(skip)]
+    pub quiet: bool, // Leaves events out of the log, for simulations
+}
+
+// Things that happen during play, kept in the history for statistics
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    Roll {
+        player: usize,
+        value: u32,
+    },
+    // Where the roll left the player, after arrows and effects
+    Moved {
+        player: usize,
+        to: u32,
+    },
+    Ladder {
+        player: usize,
+        from: u32,
+        to: u32,
+    },
+    Snake {
+        player: usize,
+        from: u32,
+        to: u32,
+    },
+    // A snake shield was used
+    Shielded {
+        player: usize,
+        square: u32,
+    },
+    Effect {
+        player: usize,
+        square: u32,
+        effect: SquareEffect,
+    },
+    SkipTurn {
+        player: usize,
+    },
+    Win {
+        player: usize,
+    },
 }
 
 impl GameState {
     pub fn new() -> Self {
+        Self::with_seed(Self::generate_random_seed())
+    }
+
+    // A new game on the board the seed gives, so the same seed always gives the same game
+    pub fn with_seed(seed: BigInt) -> Self {
+        Self::with_settings(GameSettings {
+            game_id: seed,
+            ..GameSettings::default()
+        })
+    }
+
+    // A new game with these settings, on the board their game_id seeds
+    pub fn with_settings(settings: GameSettings) -> Self {
         let colors: Vec<u32> = vec![
             0x0066FF6F, // Blue
             0x00AA006F, // Green
@@ -42,87 +132,556 @@ impl GameState {
             0x00AAAA6F, // Cyan
         ];
 
-        let random_seed = Self::generate_random_seed();
-        let arrows = Self::generate_arrow_pairs(random_seed.clone());
+        let mut settings = settings;
+        Self::clamp_settings(&mut settings);
+        let seed = settings.game_id.clone();
+        let grid_size = settings.grid_size;
+        let arrows = Self::generate_arrow_pairs(seed.clone(), grid_size, settings.difficulty);
+        let special_squares = if settings.rules.special_squares {
+            Self::generate_special_squares(seed, grid_size, &arrows)
+        } else {
+            Vec::new()
+        };
 
         Self {
-            arrows: arrows, // Initialize with an empty vector
-            users: [
-                User::new(
-                    0,                      // Default user ID
-                    "Player 1".to_string(), // Default user name
-                    1,                      // Starting position
-                ),
-                User::new(
-                    1,                      // Default user ID
-                    "Player 2".to_string(), // Default user name
-                    1,                      // Starting position
-                ),
-            ],
-            settings: GameSettings {
-                game_id: Self::generate_random_seed(), // Randomly generated or assigned
-                difficulty: "Normal".to_string(),
-                sound_enabled: true,
-                max_players: 4,
-            },
-            colors: colors,
+            arrows, // Initialize with an empty vector
+            special_squares,
+            users: Self::create_users(&settings),
+            settings,
+            colors,
             //board_type: GameBoard::SquareBoard,
-            grid_size: 10, // Default board size
+            grid_size,
             //user_position: 1, // Default user position (starting square)
             new_game: true, // Default user position (starting square)
+            current_player: 0,
+            winner: None,
+            pending_dice: Vec::new(),
+            turn_dice: None,
+            extra_die: false,
+            turn: 0,
+            dig_ledger: DigLedger::default(),
+            history: Vec::new(),
+            quiet: false,
         }
     }
 
+    // One user per configured seat, all waiting off the board on square 0
+    fn create_users(settings: &GameSettings) -> Vec<User> {
+        settings
+            .players
+            .iter()
+            .enumerate()
+            .map(|(user_id, profile)| {
+                let mut user = User::new(user_id as u32, profile.name.clone(), 0);
+                user.color = profile.color;
+                user.controller = profile.controller;
+                user
+            })
+            .collect()
+    }
+
+    // Applies changed names and colours to the users without restarting the game
+    pub fn update_players(&mut self, players: Vec<PlayerProfile>) {
+        for (user, profile) in self.users.iter_mut().zip(&players) {
+            user.name = profile.name.clone();
+            user.color = profile.color;
+            user.controller = profile.controller;
+        }
+        self.settings.players = players;
+    }
+
     pub fn move_player(&mut self, new_square: u32, player_id: i32) {
         // Placeholder for player movement logic
         println!("Moving user to square {}", new_square);
         self.users[player_id as usize].position = new_square;
     }
 
-    pub fn advance_player(&mut self, count: u32, player_id: i32) {
-        if self.new_game {
-            self.users[player_id as usize].position = 0;
-            self.new_game = false; // Set to false after the first move
+    // Moves the player and applies arrows and square effects, returns true if they roll again
+    pub fn advance_player(&mut self, count: u32, player_id: i32) -> bool {
+        let id = player_id as usize;
+        self.new_game = false; // Set to false after the first move
+
+        let start_position = self.users[id].position;
+        self.users[id].position =
+            self.settings
+                .rules
+                .overshoot
+                .land(start_position, count, self.last_square());
+
+        self.follow_arrows(id);
+
+        // Effects only trigger when the player actually moved onto the square
+        if self.users[id].position == start_position {
+            return false;
+        }
+        self.apply_square_effect(id)
+    }
+
+    fn follow_arrows(&mut self, id: usize) {
+        for (from, to) in self.arrows.clone() {
+            if self.users[id].position == from {
+                // Arrows pointing back are snakes, a shield keeps the player where they are
+                if to < from && self.users[id].shields > 0 {
+                    self.users[id].shields -= 1;
+                    self.record(GameEvent::Shielded {
+                        player: id,
+                        square: from,
+                    });
+                    continue;
+                }
+                self.users[id].position = to;
+                self.record(if to < from {
+                    GameEvent::Snake {
+                        player: id,
+                        from,
+                        to,
+                    }
+                } else {
+                    GameEvent::Ladder {
+                        player: id,
+                        from,
+                        to,
+                    }
+                });
+            }
         }
+    }
 
-        if self.users[player_id as usize].position + count <= (self.grid_size * self.grid_size) {
-            self.users[player_id as usize].position =
-                self.users[player_id as usize].position.wrapping_add(count);
+    // Adds the event to the history and the log
+    fn record(&mut self, event: GameEvent) {
+        if !self.quiet {
+            let name = |player: &usize| &self.users[*player].name;
+            match &event {
+                GameEvent::Roll { player, value } => println!("{} rolls a {}", name(player), value),
+                GameEvent::Ladder { player, from, to } | GameEvent::Snake { player, from, to } => {
+                    println!("{} follows the arrow from {} to {}", name(player), from, to)
+                }
+                GameEvent::Shielded { player, square } => {
+                    println!("{} uses a snake shield on square {}", name(player), square)
+                }
+                GameEvent::Effect {
+                    player,
+                    square,
+                    effect,
+                } => println!(
+                    "{} landed on a {:?} square at {}",
+                    name(player),
+                    effect,
+                    square
+                ),
+                GameEvent::SkipTurn { player } => println!("{} skips this turn", name(player)),
+                GameEvent::Win { player } => println!("{} wins!", name(player)),
+                GameEvent::Moved { .. } => {}
+            }
         }
+        self.history.push(event);
+    }
+
+    fn apply_square_effect(&mut self, id: usize) -> bool {
+        let Some(effect) = self.square_effect(self.users[id].position) else {
+            return false;
+        };
+        self.record(GameEvent::Effect {
+            player: id,
+            square: self.users[id].position,
+            effect,
+        });
 
-        for arrow in &self.arrows {
-            if &self.users[player_id as usize].position == &arrow.0 {
-                println!(
-                    "Landed at bottom of arrow on square {}. Moving to {}.",
-                    arrow.0, arrow.1
-                );
-                self.users[player_id as usize].position = arrow.1;
+        match effect {
+            SquareEffect::SkipTurn => {
+                self.users[id].skip_turns += 1;
+                false
+            }
+            SquareEffect::RollAgain => true,
+            SquareEffect::SwapWithLeader => {
+                if let Some(leader) = self.leader(id) {
+                    let leader_position = self.users[leader].position;
+                    // Players resting on a safe square cannot be swapped
+                    if leader_position > self.users[id].position
+                        && self.square_effect(leader_position) != Some(SquareEffect::Safe)
+                    {
+                        self.users[leader].position = self.users[id].position;
+                        self.users[id].position = leader_position;
+                    }
+                }
+                false
+            }
+            SquareEffect::Teleport(target) => {
+                self.users[id].position = target;
+                self.follow_arrows(id);
+                false
+            }
+            SquareEffect::Safe => false,
+            SquareEffect::Bonus(extra) => {
+                if self.users[id].position + extra <= self.last_square() {
+                    self.users[id].position += extra;
+                    self.follow_arrows(id);
+                }
+                false
             }
         }
     }
 
-    pub fn spin(&mut self, player_id: i32) {
-        self.advance_player(rand::rng().random_range(1..=5), player_id);
+    pub fn square_effect(&self, square: u32) -> Option<SquareEffect> {
+        self.special_squares
+            .iter()
+            .find(|(sq, _)| *sq == square)
+            .map(|(_, effect)| *effect)
+    }
+
+    // The player furthest along the board, other than the given one
+    fn leader(&self, id: usize) -> Option<usize> {
+        (0..self.users.len())
+            .filter(|other| *other != id)
+            .max_by_key(|other| self.users[*other].position)
+    }
+
+    pub fn last_square(&self) -> u32 {
+        self.grid_size * self.grid_size
+    }
+
+    // Plays the current player's turn and passes the turn to the next player.
+    // A human choosing between dice leaves the turn open until choose_die is called.
+    pub fn take_turn(&mut self) {
+        if self.winner.is_some() || !self.pending_dice.is_empty() {
+            return;
+        }
+
+        let id = self.current_player;
+        if self.users[id].skip_turns > 0 {
+            self.users[id].skip_turns -= 1;
+            self.record(GameEvent::SkipTurn { player: id });
+            self.end_turn();
+            return;
+        }
+
+        if let Controller::Ai(bot) = self.users[id].controller
+            && self.users[id].rerolls > 0
+            && ai::strategy_for(bot).use_reroll(self, id)
+        {
+            self.use_reroll();
+        }
+        self.spin();
+    }
+
+    // Spends one of the current player's re-roll tokens on the coming roll, which then
+    // rolls an extra die to pick from. Returns false if there is nothing to spend it on.
+    pub fn use_reroll(&mut self) -> bool {
+        let id = self.current_player;
+        if self.winner.is_some()
+            || !self.pending_dice.is_empty()
+            || self.extra_die
+            || self.users[id].rerolls == 0
+            || self.users[id].skip_turns > 0
+        {
+            return false;
+        }
+        self.users[id].rerolls -= 1;
+        self.extra_die = true;
+        if !self.quiet {
+            println!("{} spends a re-roll token", self.users[id].name);
+        }
+        true
+    }
+
+    // Takes in a Dig round the player handed in once the proof checks out, and gives them
+    // the reward when its output meets the target. Rounds that miss only carry the chain on.
+    pub fn award_find(
+        &mut self,
+        player: usize,
+        proof: &VdfProof,
+    ) -> Result<Option<Reward>, String> {
+        if player >= self.users.len() {
+            return Err(format!("no player {}", player + 1));
+        }
+        let config = &self.settings.vdf;
+        if proof.y.discriminant() != config.discriminant() {
+            return Err("the proof is for another class group".to_string());
+        }
+        self.dig_ledger.check(config, proof)?;
+        if !vdf::verify_proof(proof) {
+            return Err("the proof does not verify".to_string());
+        }
+        self.dig_ledger.record(proof);
+        if !config.target.matches(&proof.y) {
+            return Ok(None);
+        }
+
+        // The output decides the reward, so it cannot be picked
+        let reward = if !proof.y.a.test_bit(0) {
+            Reward::SnakeShield
+        } else {
+            Reward::Reroll
+        };
+        self.users[player].add_reward(reward);
+        println!("{} digs up a {}", self.users[player].name, reward);
+        Ok(Some(reward))
+    }
+
+    // Plays the turn with dice drawn from a seed the players agreed on, so anyone holding
+    // the state from before the turn can replay it and get the same result
+    pub fn take_turn_with_seed(&mut self, seed: Seed) {
+        if self.winner.is_some() || !self.pending_dice.is_empty() {
+            return;
+        }
+        self.turn_dice = Some(FairDice::new(seed));
+        self.take_turn();
+    }
+
+    // True when the current player is computer controlled and should be played automatically
+    pub fn ai_to_move(&self) -> bool {
+        self.winner.is_none()
+            && self
+                .users
+                .get(self.current_player)
+                .is_some_and(|user| user.controller.is_ai())
+    }
+
+    pub fn spin(&mut self) {
+        let id = self.current_player;
+        let dice = self.roll_dice();
+        if dice.len() == 1 {
+            self.play_die(dice[0]);
+            return;
+        }
+
+        match self.users[id].controller {
+            Controller::Human => self.pending_dice = dice,
+            Controller::Ai(bot) => {
+                let choice = ai::strategy_for(bot).choose_die(self, id, &dice);
+                if !self.quiet {
+                    println!("{} picks the {}", self.users[id].name, dice[choice]);
+                }
+                self.play_die(dice[choice]);
+            }
+        }
+    }
+
+    // Finishes a turn left open by spin, value must be one of the pending dice
+    pub fn choose_die(&mut self, value: u32) {
+        if !self.pending_dice.contains(&value) {
+            return;
+        }
+        self.pending_dice.clear();
+        self.play_die(value);
+    }
+
+    fn roll_dice(&mut self) -> Vec<u32> {
+        let die_sides = self.settings.rules.die_sides.max(1);
+        let mut count = if self.settings.rules.choose_die { 2 } else { 1 };
+        // The token only covers the first roll, not rolls again from square effects
+        if self.extra_die {
+            count += 1;
+            self.extra_die = false;
+        }
+        (0..count)
+            .map(|_| match &mut self.turn_dice {
+                Some(dice) => dice.roll(die_sides),
+                None => rand::rng().random_range(1..=die_sides),
+            })
+            .collect()
+    }
+
+    fn play_die(&mut self, value: u32) {
+        let id = self.current_player;
+        self.record(GameEvent::Roll { player: id, value });
+        let rolls_again = self.advance_player(value, id as i32);
+        self.record(GameEvent::Moved {
+            player: id,
+            to: self.users[id].position,
+        });
+        if rolls_again && self.users[id].position != self.last_square() {
+            if !self.quiet {
+                println!("{} rolls again", self.users[id].name);
+            }
+            self.spin();
+        } else {
+            self.end_turn();
+        }
+    }
+
+    fn end_turn(&mut self) {
+        self.turn_dice = None;
+        self.turn += 1;
+        let id = self.current_player;
+        if self.users[id].position == self.last_square() {
+            self.record(GameEvent::Win { player: id });
+            self.winner = Some(id);
+            return;
+        }
+        self.current_player = (id + 1) % self.users.len();
     }
 
     pub fn reset(&mut self) {
-        // Reset the game state to initial values
+        let (seed, proof) = Self::new_seed(&self.settings);
+        self.reset_with_seed(seed, proof);
+    }
+
+    // reset with a seed from new_seed, which can be worked out on another thread
+    pub fn reset_with_seed(&mut self, random_seed: BigInt, seed_proof: Option<VdfProof>) {
+        // Reset the game state to initial values, picking up any changed settings
         self.new_game = true; // Reset new game flag
+        Self::clamp_settings(&mut self.settings);
+        self.grid_size = self.settings.grid_size;
         self.arrows.clear();
-        let random_seed = Self::generate_random_seed();
-        self.settings.game_id = random_seed.clone(); // Reset game ID to a new random seed
-        let new_arrows = Self::generate_arrow_pairs(random_seed.clone());
+        self.settings.game_id = random_seed.clone(); // Reset game ID to the new seed
+        self.settings.seed_proof = seed_proof;
+        let new_arrows = Self::generate_arrow_pairs(
+            random_seed.clone(),
+            self.grid_size,
+            self.settings.difficulty,
+        );
+        self.special_squares = if self.settings.rules.special_squares {
+            Self::generate_special_squares(random_seed.clone(), self.grid_size, &new_arrows)
+        } else {
+            Vec::new()
+        };
         self.arrows = new_arrows;
 
-        for user in &mut self.users {
-            user.position = 1; // Reset each user's state
+        // Recreate the users so changes to the player list take effect,
+        // rewards from digging carry over to the new game
+        let rewards: Vec<(u32, u32)> = self
+            .users
+            .iter()
+            .map(|user| (user.rerolls, user.shields))
+            .collect();
+        self.users = Self::create_users(&self.settings);
+        for (user, (rerolls, shields)) in self.users.iter_mut().zip(rewards) {
+            user.rerolls = rerolls;
+            user.shields = shields;
         }
+        self.current_player = 0;
+        self.winner = None;
+        self.pending_dice.clear();
+        self.turn_dice = None;
+        self.extra_die = false;
+        self.turn = 0;
+        self.history.clear();
         //self.user_position = 1; // Default user position (starting square)
     }
 
+    // Seed for the next board, with its proof when the settings ask for a VDF seed.
+    // VDF and fair seeds take a while, see BoardSeed::is_slow.
+    pub fn new_seed(settings: &GameSettings) -> (BigInt, Option<VdfProof>) {
+        match &settings.board_seed {
+            BoardSeed::Random => (Self::generate_random_seed(), None),
+            BoardSeed::Vdf {
+                input,
+                log_iterations,
+            } => {
+                let t = BoardSeed::iterations(*log_iterations);
+                println!("Evaluating the VDF over \"{}\" for {} iterations", input, t);
+                let proof = vdf::prove(&vdf::input_from_text(input), t);
+                (vdf::seed_from_output(&proof.y), Some(proof))
+            }
+            BoardSeed::Fair {
+                min_turns,
+                max_turns,
+                max_advantage,
+            } => (
+                Self::fair_seed(settings, *min_turns, *max_turns, *max_advantage),
+                None,
+            ),
+        }
+    }
+
+    // Tries random seeds for a board that meets the fairness target, the closest one
+    // found is used if none does within FAIR_SEED_TRIES
+    fn fair_seed(
+        settings: &GameSettings,
+        min_turns: u32,
+        max_turns: u32,
+        max_advantage: u32,
+    ) -> BigInt {
+        let players = settings.players.len().max(1);
+        let mut best: Option<(f64, BigInt)> = None;
+        for _ in 0..FAIR_SEED_TRIES {
+            let seed = Self::generate_random_seed();
+            let trial = Self::with_settings(GameSettings {
+                game_id: seed.clone(),
+                ..settings.clone()
+            });
+            let miss = analysis::analyze(&trial).fairness_miss(
+                players,
+                min_turns,
+                max_turns,
+                max_advantage,
+            );
+            if miss == 0.0 {
+                return seed;
+            }
+            if best.as_ref().is_none_or(|(closest, _)| miss < *closest) {
+                best = Some((miss, seed));
+            }
+        }
+        println!(
+            "No board within {} to {} turns and a {}% edge in {} tries, using the closest",
+            min_turns, max_turns, max_advantage, FAIR_SEED_TRIES
+        );
+        best.map(|(_, seed)| seed)
+            .unwrap_or_else(Self::generate_random_seed)
+    }
+
+    // Checks that a VDF seeded board is the one its public input gives: the proof holds,
+    // the seed is the VDF output, and the arrows and special squares follow from the seed
+    pub fn verify_board(&self) -> Result<(), String> {
+        let BoardSeed::Vdf {
+            input,
+            log_iterations,
+        } = &self.settings.board_seed
+        else {
+            return Err("the board was not seeded from a VDF".to_string());
+        };
+        let Some(proof) = &self.settings.seed_proof else {
+            return Err("no VDF proof is stored with the game".to_string());
+        };
+
+        if proof.x != vdf::input_from_text(input)
+            || proof.t != BoardSeed::iterations(*log_iterations)
+        {
+            return Err("the proof is for a different input".to_string());
+        }
+        // Boards are always seeded in the built-in class group
+        if !vdf::verify(&proof.x, proof.t, &proof.y, &proof.pi) {
+            return Err("the VDF proof does not verify".to_string());
+        }
+
+        let seed = vdf::seed_from_output(&proof.y);
+        if self.settings.game_id != seed {
+            return Err("the game id is not the VDF output".to_string());
+        }
+        let arrows =
+            Self::generate_arrow_pairs(seed.clone(), self.grid_size, self.settings.difficulty);
+        if self.arrows != arrows {
+            return Err("the arrows do not match the seed".to_string());
+        }
+        let special_squares = if self.settings.rules.special_squares {
+            Self::generate_special_squares(seed, self.grid_size, &arrows)
+        } else {
+            Vec::new()
+        };
+        if self.special_squares != special_squares {
+            return Err("the special squares do not match the seed".to_string());
+        }
+        Ok(())
+    }
+
     // Temporary auxiliraty function to generate a seed
     fn generate_random_seed() -> BigInt {
-        let mut rng = rand::rng();
+        Self::seed_digits(&mut rand::rng())
+    }
+
+    // A short seed, such as a number typed in, spread out to a full length seed so its
+    // board gets special squares too. Seeds that are long enough are kept as they are.
+    pub fn full_seed(seed: BigInt) -> BigInt {
+        if seed.to_string().len() >= SEED_DIGITS {
+            return seed;
+        }
+        Self::seed_digits(&mut Self::board_rng(&seed))
+    }
+
+    // A 192 digit seed drawn from rng
+    fn seed_digits(rng: &mut impl Rng) -> BigInt {
         let mut digits = String::new();
 
         // First digit must be 1-9 to ensure 192 digits
@@ -206,49 +765,401 @@ impl GameState {
     }
     */
 
-    // Update game settings
-    pub fn update_settings(&mut self, settings: GameSettings) {
+    // Update game settings, board and player changes take effect on the next reset
+    pub fn update_settings(&mut self, mut settings: GameSettings) {
+        Self::clamp_settings(&mut settings);
+        settings
+            .players
+            .truncate(settings.max_players.max(1) as usize);
+        if settings.players.is_empty() {
+            settings.players = GameSettings::default().players;
+        }
+        // A different search starts a new chain
+        if settings.vdf != self.settings.vdf {
+            self.dig_ledger = DigLedger::default();
+        }
         self.settings = settings;
     }
 
-    //pub fn generate_arrow_pairs(&mut self, seed: BigInt) {
-    fn generate_arrow_pairs(seed: BigInt) -> Vec<(u32, u32)> {
-        let mut arrows = Vec::new();
+    // Keeps the board and die sizes to what the board and the rules can handle
+    fn clamp_settings(settings: &mut GameSettings) {
+        settings.grid_size = settings.grid_size.clamp(MIN_GRID_SIZE, MAX_GRID_SIZE);
+        settings.rules.die_sides = settings.rules.die_sides.clamp(1, MAX_DIE_SIDES);
+    }
+
+    // Arrows are drawn from an RNG seeded with the game seed, so a seed always gives the same board
+    fn generate_arrow_pairs(
+        seed: BigInt,
+        grid_size: u32,
+        difficulty: Difficulty,
+    ) -> Vec<(u32, u32)> {
+        let mut arrows: Vec<(u32, u32)> = Vec::new();
+        let mut rng = Self::board_rng(&seed);
+        let profile = difficulty.profile();
+        let last_square = grid_size * grid_size;
+
+        let num_arrows = ((last_square as f32 * profile.density).round() as u32).max(1);
+        let num_snakes = (num_arrows as f32 * profile.snake_ratio).round() as u32;
+
+        // Arrows always span at least one row and must fit between the first and last square
+        let max_rows = profile
+            .max_rows
+            .clamp(1, grid_size.saturating_sub(2).max(1));
+        let min_rows = profile.min_rows.clamp(1, max_rows);
+
+        // The start and finish squares never hold an arrow end
+        let mut used = vec![1, last_square];
+        let mut attempts = 0;
+
+        while (arrows.len() as u32) < num_arrows && attempts < num_arrows.saturating_mul(50) {
+            attempts += 1;
+
+            let length = rng.random_range(min_rows * grid_size..=max_rows * grid_size);
+            if length + 2 >= last_square {
+                continue;
+            }
+
+            let low = rng.random_range(2..last_square - length);
+            let high = low + length;
+            if used.contains(&low) || used.contains(&high) {
+                continue;
+            }
+
+            if (arrows.len() as u32) < num_snakes {
+                arrows.push((high, low));
+            } else {
+                arrows.push((low, high));
+            }
+            used.push(low);
+            used.push(high);
+        }
+        arrows
+    }
+
+    fn board_rng(seed: &BigInt) -> StdRng {
+        let digest = Sha256::digest(&seed.to_bytes());
+        let mut rng_seed = [0u8; 32];
+        rng_seed.copy_from_slice(&digest);
+        StdRng::from_seed(rng_seed)
+    }
+
+    // Special squares are read from chunks of the seed digits from digit 48 on, so seeds
+    // much shorter than SEED_DIGITS get none, see full_seed
+    fn generate_special_squares(
+        seed: BigInt,
+        grid_size: u32,
+        arrows: &[(u32, u32)],
+    ) -> Vec<(u32, SquareEffect)> {
+        let mut specials: Vec<(u32, SquareEffect)> = Vec::new();
         let seed_str = seed.to_string();
+        let last_square = grid_size * grid_size;
 
-        let num_pairs = ((&seed % BigInt::from(6)) + BigInt::from(5))
+        let num_specials = ((&seed % BigInt::from(4)) + BigInt::from(3))
             .to_string()
             .parse::<u32>()
-            .unwrap_or(5)
-            .clamp(5, 10);
-
-        //let mut pairs = Vec::new();
+            .unwrap_or(3);
 
-        for i in 1..num_pairs + 1 {
-            let start = (i * 4) as usize;
+        for i in 0..num_specials {
+            let start = (48 + i * 4) as usize;
 
             if start + 4 > seed_str.len() {
                 break;
             }
 
             let chunk = &seed_str[start..start + 4];
-            let first = chunk[0..2].parse::<u32>().unwrap_or(1).max(1).min(100);
-            let second = chunk[2..4].parse::<u32>().unwrap_or(1).max(1).min(100);
+            let square = chunk[0..2].parse::<u32>().unwrap_or(0) % (last_square - 2) + 2;
+            let kind = chunk[2..3].parse::<u32>().unwrap_or(0);
+            let amount = chunk[3..4].parse::<u32>().unwrap_or(0);
 
-            let first_decade = first / 10;
-            let second_decade = second / 10;
+            // Keep clear of arrow ends and squares that already have an effect
+            if arrows
+                .iter()
+                .any(|(from, to)| *from == square || *to == square)
+                || specials.iter().any(|(sq, _)| *sq == square)
+            {
+                continue;
+            }
 
-            let final_second = if first_decade != second_decade {
-                second
-            } else if second >= first {
-                (second + 10) % 100
-            } else {
-                (second.wrapping_sub(10)) % 100
+            let effect = match kind % 6 {
+                0 => SquareEffect::SkipTurn,
+                1 => SquareEffect::RollAgain,
+                2 => SquareEffect::SwapWithLeader,
+                3 => {
+                    let target = 2 + amount * (last_square - 3) / 9;
+                    if target == square {
+                        continue;
+                    }
+                    SquareEffect::Teleport(target)
+                }
+                4 => SquareEffect::Safe,
+                _ => SquareEffect::Bonus(amount % 3 + 1),
             };
 
-            arrows.push((first, final_second));
+            specials.push((square, effect));
         }
-        arrows
+        specials
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Overshoot;
+    use crate::vdf::{DIG_ROUND, Discriminant, VdfConfig};
+    use std::sync::atomic::AtomicBool;
+
+    // A game digging in a small class group, so rounds are quick to prove
+    fn digging_game() -> GameState {
+        GameState::with_settings(GameSettings {
+            vdf: VdfConfig {
+                discriminant: Discriminant::Generated { bits: 256 },
+                input: "dig test".to_string(),
+                ..VdfConfig::default()
+            },
+            ..GameSettings::default()
+        })
+    }
+
+    fn dig(state: &GameState, x: &BigInt, t: u64) -> VdfProof {
+        let never = AtomicBool::new(false);
+        let disc = state.settings.vdf.discriminant();
+        let (y, pi) = vdf::eval_with_progress(&disc, x, t, &never, |_| {}).unwrap();
+        VdfProof {
+            x: x.clone(),
+            t,
+            y,
+            pi,
+        }
+    }
+
+    #[test]
+    fn test_dig_rounds_must_follow_the_chain() {
+        let mut state = digging_game();
+        let input = state.settings.vdf.input();
+
+        // A proof with no squarings verifies but is not a round
+        let empty = dig(&state, &input, 0);
+        assert!(vdf::verify_proof(&empty));
+        assert!(state.award_find(0, &empty).is_err());
+
+        // Nor does a round from an input of the finder's choosing count
+        let elsewhere = dig(&state, &vdf::input_from_text("elsewhere"), DIG_ROUND);
+        assert!(state.award_find(0, &elsewhere).is_err());
+
+        let first = dig(&state, &input, DIG_ROUND);
+        assert!(state.award_find(0, &first).unwrap().is_some());
+        // Handing the same round in again, even for another player, earns nothing
+        assert!(state.award_find(0, &first).is_err());
+        assert!(state.award_find(1, &first).is_err());
+        let rewards = |user: &User| user.rerolls + user.shields;
+        assert_eq!(rewards(&state.users[0]), 1);
+        assert_eq!(rewards(&state.users[1]), 0);
+
+        // The chain carries on from the first round's output
+        let second = dig(&state, &vdf::next_input(&first.y), DIG_ROUND);
+        assert!(state.award_find(1, &second).unwrap().is_some());
+        assert_eq!(
+            state.dig_ledger.head(&state.settings.vdf),
+            vdf::next_input(&second.y)
+        );
+    }
+
+    // Player 0 part way along a board with no arrows or special squares
+    fn plain_game(position: u32) -> GameState {
+        let mut state = GameState::with_seed(BigInt::from(1));
+        state.arrows.clear();
+        state.special_squares.clear();
+        state.new_game = false;
+        state.users[0].position = position;
+        state
+    }
+
+    #[test]
+    fn test_every_seat_starts_off_the_board() {
+        // The analysis assumes this, a seat starting ahead would win more often
+        let mut state = GameState::with_seed(BigInt::from(1));
+        state.arrows.clear();
+        assert!(state.users.iter().all(|user| user.position == 0));
+        state.advance_player(3, 0);
+        state.advance_player(3, 1);
+        assert_eq!(state.users[0].position, state.users[1].position);
+    }
+
+    #[test]
+    fn test_overshoot_rules() {
+        let mut state = plain_game(98);
+        let last_square = state.last_square();
+        state.advance_player(5, 0);
+        assert_eq!(state.users[0].position, 98);
+
+        let mut state = plain_game(98);
+        state.settings.rules.overshoot = Overshoot::Finish;
+        state.advance_player(5, 0);
+        assert_eq!(state.users[0].position, last_square);
+
+        let mut state = plain_game(98);
+        state.settings.rules.overshoot = Overshoot::Bounce;
+        state.advance_player(5, 0);
+        assert_eq!(state.users[0].position, last_square - 3);
+        // Landing exactly on the last square is not an overshoot
+        state.advance_player(3, 0);
+        assert_eq!(state.users[0].position, last_square);
+    }
+
+    #[test]
+    fn test_bounce_with_a_die_bigger_than_the_board() {
+        let mut state = plain_game(15);
+        state.grid_size = 4;
+        state.settings.rules.overshoot = Overshoot::Bounce;
+        // 15 + 40 goes to 16, back to 0 and out again to 9
+        state.advance_player(40, 0);
+        assert_eq!(state.users[0].position, 9);
+    }
+
+    #[test]
+    fn test_sizes_are_clamped() {
+        let mut settings = GameSettings {
+            grid_size: 50,
+            ..GameSettings::default()
+        };
+        settings.rules.die_sides = 1000;
+        let mut state = GameState::with_settings(settings.clone());
+        assert_eq!(state.grid_size, MAX_GRID_SIZE);
+        assert_eq!(state.settings.rules.die_sides, MAX_DIE_SIDES);
+
+        // A reset picks the settings up the same way
+        state.settings = settings;
+        state.reset();
+        assert_eq!(state.grid_size, MAX_GRID_SIZE);
+        assert_eq!(state.settings.grid_size, MAX_GRID_SIZE);
+        assert_eq!(state.settings.rules.die_sides, MAX_DIE_SIDES);
+    }
+
+    // Player 0 on square 2 with the effect waiting on square 5
+    fn effect_game(effect: SquareEffect) -> GameState {
+        let mut state = plain_game(2);
+        state.special_squares = vec![(5, effect)];
+        state
+    }
+
+    #[test]
+    fn test_teleport_and_bonus_move_on() {
+        let mut state = effect_game(SquareEffect::Teleport(40));
+        state.arrows = vec![(40, 60)];
+        assert!(!state.advance_player(3, 0));
+        // Arrows are followed from where the teleport lands
+        assert_eq!(state.users[0].position, 60);
+
+        let mut state = effect_game(SquareEffect::Bonus(3));
+        assert!(!state.advance_player(3, 0));
+        assert_eq!(state.users[0].position, 8);
+    }
+
+    #[test]
+    fn test_skip_turn_and_roll_again() {
+        let mut state = effect_game(SquareEffect::SkipTurn);
+        assert!(!state.advance_player(3, 0));
+        assert_eq!(state.users[0].skip_turns, 1);
+        // The next turn is used up without moving
+        state.take_turn();
+        assert_eq!(state.users[0].position, 5);
+        assert_eq!(state.users[0].skip_turns, 0);
+        assert_eq!(state.current_player, 1);
+
+        let mut state = effect_game(SquareEffect::RollAgain);
+        assert!(state.advance_player(3, 0));
+        // Only landing there counts, not staying put on it
+        state.settings.rules.overshoot = Overshoot::Stay;
+        state.users[0].position = 5;
+        assert!(!state.advance_player(state.last_square(), 0));
+    }
+
+    #[test]
+    fn test_swap_with_leader() {
+        let mut state = effect_game(SquareEffect::SwapWithLeader);
+        state.users[1].position = 30;
+        state.advance_player(3, 0);
+        assert_eq!(state.users[0].position, 30);
+        assert_eq!(state.users[1].position, 5);
+
+        // A leader resting on a safe square keeps their place
+        let mut state = effect_game(SquareEffect::SwapWithLeader);
+        state.special_squares.push((30, SquareEffect::Safe));
+        state.users[1].position = 30;
+        state.advance_player(3, 0);
+        assert_eq!(state.users[0].position, 5);
+        assert_eq!(state.users[1].position, 30);
+    }
+
+    #[test]
+    fn test_shield_stops_one_snake() {
+        let mut state = plain_game(2);
+        state.arrows = vec![(5, 1), (9, 3)];
+        state.users[0].shields = 1;
+        state.advance_player(3, 0);
+        assert_eq!(state.users[0].position, 5);
+        assert_eq!(state.users[0].shields, 0);
+        assert!(matches!(
+            state.history.last(),
+            Some(GameEvent::Shielded { square: 5, .. })
+        ));
+
+        // With the shield used up the next snake bites
+        state.advance_player(4, 0);
+        assert_eq!(state.users[0].position, 3);
+    }
+
+    fn vdf_seeded_game() -> GameState {
+        let mut state = GameState::with_settings(GameSettings {
+            board_seed: BoardSeed::Vdf {
+                input: "board test".to_string(),
+                log_iterations: 4,
+            },
+            ..GameSettings::default()
+        });
+        state.reset();
+        state
+    }
+
+    #[test]
+    fn test_vdf_seeded_board_verifies() {
+        let state = vdf_seeded_game();
+        assert!(state.settings.seed_proof.is_some());
+        assert_eq!(state.verify_board(), Ok(()));
+
+        // The same seed worked out elsewhere, as the window does, gives the same board
+        let (seed, proof) = GameState::new_seed(&state.settings);
+        let mut elsewhere = GameState::new();
+        elsewhere.settings = state.settings.clone();
+        elsewhere.reset_with_seed(seed, proof);
+        assert_eq!(elsewhere.arrows, state.arrows);
+        assert_eq!(elsewhere.verify_board(), Ok(()));
+    }
+
+    #[test]
+    fn test_tampered_board_does_not_verify() {
+        let state = vdf_seeded_game();
+
+        let mut moved_arrow = state.clone();
+        moved_arrow.arrows[0].1 += 1;
+        assert!(moved_arrow.verify_board().is_err());
+
+        let mut other_seed = state.clone();
+        other_seed.settings.game_id = &state.settings.game_id + BigInt::from(1);
+        assert!(other_seed.verify_board().is_err());
+
+        let mut other_input = state.clone();
+        other_input.settings.board_seed = BoardSeed::Vdf {
+            input: "another board".to_string(),
+            log_iterations: 4,
+        };
+        assert!(other_input.verify_board().is_err());
+
+        let mut forged = state.clone();
+        if let Some(proof) = &mut forged.settings.seed_proof {
+            proof.pi = proof.y.clone();
+        }
+        assert!(forged.verify_board().is_err());
     }
 }
 
@@ -0,0 +1,111 @@
+// How often tokens end up on each square, for the overlay drawn over the board
+use crate::analysis::BoardAnalysis;
+use crate::game_state::{GameEvent, GameState};
+use std::fmt;
+
+// Where the overlay's numbers come from, the Heatmap button steps through these
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeatSource {
+    #[default]
+    Off,
+    Played,   // Moves in this game's history
+    Expected, // Turns ending on each square from the board analysis
+}
+
+impl HeatSource {
+    pub fn next(self) -> Self {
+        match self {
+            HeatSource::Off => HeatSource::Played,
+            HeatSource::Played => HeatSource::Expected,
+            HeatSource::Expected => HeatSource::Off,
+        }
+    }
+}
+
+impl fmt::Display for HeatSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeatSource::Off => write!(f, "Off"),
+            HeatSource::Played => write!(f, "Moves"),
+            HeatSource::Expected => write!(f, "Expected"),
+        }
+    }
+}
+
+// Count for each square, index is the square number, or None while the overlay is off
+pub fn square_heat(
+    source: HeatSource,
+    state: &GameState,
+    analysis: Option<&BoardAnalysis>,
+) -> Option<Vec<f64>> {
+    let mut heat = vec![0.0; state.last_square() as usize + 1];
+    match source {
+        HeatSource::Off => return None,
+        HeatSource::Played => {
+            for event in &state.history {
+                if let GameEvent::Moved { to, .. } = event
+                    && let Some(count) = heat.get_mut(*to as usize)
+                {
+                    *count += 1.0;
+                }
+            }
+        }
+        HeatSource::Expected => {
+            let visits = &analysis?.visits;
+            for (count, visits) in heat.iter_mut().zip(visits) {
+                *count = *visits;
+            }
+        }
+    }
+    Some(heat)
+}
+
+// Tint for a square from none at 0 through yellow to red at 1, in 0xRRGGBBAA format
+pub fn heat_color(level: f64) -> u32 {
+    let level = level.clamp(0.0, 1.0);
+    if level == 0.0 {
+        return 0;
+    }
+    let green = (0xD0 as f64 * (1.0 - level)) as u32;
+    let alpha = (60.0 + 150.0 * level) as u32;
+    0xFF000000 | (green << 16) | alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+    use curv::BigInt;
+
+    #[test]
+    fn test_heat_from_history_and_analysis() {
+        let mut state = GameState::with_seed(BigInt::from(3));
+        assert_eq!(square_heat(HeatSource::Off, &state, None), None);
+
+        state.history = vec![
+            GameEvent::Moved { player: 0, to: 4 },
+            GameEvent::Roll {
+                player: 1,
+                value: 4,
+            },
+            GameEvent::Moved { player: 1, to: 4 },
+            GameEvent::Moved { player: 0, to: 9 },
+        ];
+        let heat = square_heat(HeatSource::Played, &state, None).unwrap();
+        assert_eq!(heat.len(), 101);
+        assert_eq!((heat[4], heat[9], heat[5]), (2.0, 1.0, 0.0));
+
+        let analysis = analysis::analyze(&state);
+        assert_eq!(square_heat(HeatSource::Expected, &state, None), None);
+        let heat = square_heat(HeatSource::Expected, &state, Some(&analysis)).unwrap();
+        assert_eq!(heat, analysis.visits);
+    }
+
+    #[test]
+    fn test_colors_run_from_clear_to_red() {
+        assert_eq!(heat_color(0.0), 0);
+        assert_eq!(heat_color(1.0), 0xFF0000D2);
+        assert_eq!(heat_color(2.0), heat_color(1.0));
+        assert!(heat_color(0.5) & 0xFF < heat_color(1.0) & 0xFF);
+    }
+}
@@ -0,0 +1,142 @@
+// Keyboard shortcuts for the game window, read from a JSON file so they can be changed.
+// Keys are named the way winit names them ("Space", "Enter", "F5") or by the character
+// they type ("r"), with "Ctrl+" in front when Control has to be held.
+use crate::action::Action;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use winit::keyboard::{Key, ModifiersState};
+
+pub const KEYMAP_FILE: &str = "keymap.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Keymap {
+    pub bindings: BTreeMap<String, Action>, // Key name to action
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = [
+            ("Space", Action::Roll),
+            ("Enter", Action::Roll),
+            ("e", Action::Reroll),
+            ("r", Action::Reset),
+            ("Ctrl+s", Action::Save),
+            ("Ctrl+o", Action::Load),
+            ("d", Action::Dig),
+            ("h", Action::Heatmap),
+            ("Ctrl+z", Action::Undo),
+            ("u", Action::Undo),
+            ("Escape", Action::Quit),
+        ];
+        Keymap {
+            bindings: bindings
+                .iter()
+                .map(|(key, action)| (key.to_string(), *action))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn save_to_file(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let json_string = serde_json::to_string_pretty(self)?;
+        let mut file = fs::File::create(filename)?;
+        file.write_all(json_string.as_bytes())?;
+        println!("Keymap saved to {}", filename);
+        Ok(())
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_string = fs::read_to_string(filename)?;
+        let keymap: Self = serde_json::from_str(&json_string)?;
+        Ok(keymap)
+    }
+
+    // The keymap file if there is one, otherwise the default keys written out to edit
+    pub fn load_or_create(filename: &str) -> Self {
+        match Self::load_from_file(filename) {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                if fs::metadata(filename).is_ok() {
+                    println!("Could not read {}, using the default keys: {}", filename, e);
+                } else if let Err(e) = Self::default().save_to_file(filename) {
+                    println!("Could not write {}: {}", filename, e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn action_for(&self, key: &Key, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings.get(&key_name(key, modifiers)?).copied()
+    }
+
+    // The shortest key bound to the action, shown as a hint next to its button
+    pub fn hint(&self, action: Action) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| key.clone())
+            .min_by_key(|key| key.chars().count())
+    }
+}
+
+pub fn key_name(key: &Key, modifiers: ModifiersState) -> Option<String> {
+    let name = match key {
+        Key::Named(named) => format!("{:?}", named),
+        Key::Character(text) => text.to_lowercase(),
+        _ => return None,
+    };
+    if modifiers.control_key() {
+        Some(format!("Ctrl+{}", name))
+    } else {
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::keyboard::NamedKey;
+
+    #[test]
+    fn test_keys_map_to_actions() {
+        let keymap = Keymap::default();
+        let none = ModifiersState::empty();
+        let ctrl = ModifiersState::CONTROL;
+
+        assert_eq!(
+            keymap.action_for(&Key::Named(NamedKey::Space), none),
+            Some(Action::Roll)
+        );
+        assert_eq!(
+            keymap.action_for(&Key::Character("R".into()), none),
+            Some(Action::Reset)
+        );
+        assert_eq!(
+            keymap.action_for(&Key::Character("z".into()), ctrl),
+            Some(Action::Undo)
+        );
+        assert_eq!(keymap.action_for(&Key::Character("s".into()), none), None);
+
+        assert_eq!(keymap.hint(Action::Roll).as_deref(), Some("Enter"));
+        assert_eq!(keymap.hint(Action::Undo).as_deref(), Some("u"));
+    }
+
+    #[test]
+    fn test_keymap_files_round_trip() {
+        let mut keymap = Keymap::default();
+        keymap.bindings.insert("F5".to_string(), Action::Save);
+        keymap
+            .bindings
+            .insert("1".to_string(), Action::ChooseDie(1));
+        let path = std::env::temp_dir().join(format!("keymap_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        keymap.save_to_file(path).unwrap();
+        assert_eq!(Keymap::load_from_file(path).unwrap(), keymap);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+// Lays widgets out in rows or columns so panels fit the space they are given.
+// A stack places its items one after another with spacing between them and padding
+// around the edge. When the items do not fit they shrink towards their minimum sizes,
+// when there is room to spare the ones that grow share it out up to their maximum.
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bounds {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Bounds {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Bounds {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Vertical,   // Top to bottom
+    Horizontal, // Left to right
+}
+
+// Where items sit across the stack, a vertical stack's items are left, centred or full width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Start,
+    Center,
+    Stretch,
+}
+
+// One widget's sizes along the stack, and across it unless the stack stretches it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Item {
+    pub size: f32, // Wanted size
+    pub min: f32,  // Smallest it can be squeezed to
+    pub max: f32,  // Largest it can grow to
+    pub grow: f32, // Share of any spare room, 0 keeps the wanted size
+    pub cross: Option<f32>,
+}
+
+impl Item {
+    // Always the same size
+    pub fn fixed(size: f32) -> Self {
+        Item {
+            size,
+            min: size,
+            max: size,
+            grow: 0.0,
+            cross: None,
+        }
+    }
+
+    // The wanted size when there is room, down to min when there is not
+    pub fn shrinkable(size: f32, min: f32) -> Self {
+        Item {
+            min: min.min(size),
+            ..Item::fixed(size)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub direction: Direction,
+    pub padding: f32, // Around the edge of the stack
+    pub spacing: f32, // Between neighbouring items
+    pub align: Align,
+    items: Vec<Item>,
+}
+
+impl Stack {
+    pub fn new(direction: Direction) -> Self {
+        Stack {
+            direction,
+            padding: 0.0,
+            spacing: 0.0,
+            align: Align::Start,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn set_padding(&mut self, padding: f32) {
+        self.padding = padding;
+    }
+
+    pub fn set_spacing(&mut self, spacing: f32) {
+        self.spacing = spacing;
+    }
+
+    pub fn set_align(&mut self, align: Align) {
+        self.align = align;
+    }
+
+    // Returns the item's index in what layout gives back
+    pub fn add(&mut self, item: Item) -> usize {
+        self.items.push(item);
+        self.items.len() - 1
+    }
+
+    // Where each item goes inside bounds, in the order they were added. Bounds with
+    // no room, as a panel squeezed out by the board, give items no room either.
+    pub fn layout(&self, bounds: Bounds) -> Vec<Bounds> {
+        let bounds = Bounds {
+            width: bounds.width.max(0.0),
+            height: bounds.height.max(0.0),
+            ..bounds
+        };
+        let (main_start, main_length, cross_start, cross_length) = match self.direction {
+            Direction::Vertical => (bounds.y, bounds.height, bounds.x, bounds.width),
+            Direction::Horizontal => (bounds.x, bounds.width, bounds.y, bounds.height),
+        };
+        let gaps = self.spacing * self.items.len().saturating_sub(1) as f32;
+        let room = (main_length - self.padding * 2.0 - gaps).max(0.0);
+        let sizes = self.main_sizes(room);
+        let cross_room = (cross_length - self.padding * 2.0).max(0.0);
+
+        let mut position = main_start + self.padding;
+        self.items
+            .iter()
+            .zip(sizes)
+            .map(|(item, size)| {
+                let across = match (self.align, item.cross) {
+                    (Align::Stretch, _) | (_, None) => cross_room,
+                    (_, Some(cross)) => cross.min(cross_room),
+                };
+                let offset = match self.align {
+                    Align::Center => (cross_room - across) / 2.0,
+                    Align::Start | Align::Stretch => 0.0,
+                };
+                let main = position;
+                let cross = cross_start + self.padding + offset;
+                position += size + self.spacing;
+                match self.direction {
+                    Direction::Vertical => Bounds::new(cross, main, across, size),
+                    Direction::Horizontal => Bounds::new(main, cross, size, across),
+                }
+            })
+            .collect()
+    }
+
+    // Item sizes along the stack so that they fill room as well as their limits allow
+    fn main_sizes(&self, room: f32) -> Vec<f32> {
+        let mut sizes: Vec<f32> = self.items.iter().map(|item| item.size).collect();
+        let wanted: f32 = sizes.iter().sum();
+
+        if wanted > room {
+            // Everything squeezes by the same share of what it can give up
+            let slack: f32 = self.items.iter().map(|item| item.size - item.min).sum();
+            if slack > 0.0 {
+                let share = ((wanted - room) / slack).min(1.0);
+                for (size, item) in sizes.iter_mut().zip(&self.items) {
+                    *size -= (item.size - item.min) * share;
+                }
+            }
+            return sizes;
+        }
+
+        // Spare room goes to the growing items by weight, what one at its maximum
+        // cannot take is handed round again
+        let mut spare = room - wanted;
+        while spare > 0.01 {
+            let weights: f32 = self
+                .items
+                .iter()
+                .zip(&sizes)
+                .filter(|(item, size)| item.grow > 0.0 && **size < item.max)
+                .map(|(item, _)| item.grow)
+                .sum();
+            if weights == 0.0 {
+                break;
+            }
+            let mut given = 0.0;
+            for (size, item) in sizes.iter_mut().zip(&self.items) {
+                if item.grow > 0.0 && *size < item.max {
+                    let extra = (spare * item.grow / weights).min(item.max - *size);
+                    *size += extra;
+                    given += extra;
+                }
+            }
+            spare -= given;
+        }
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_stack_with_padding_and_spacing() {
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_padding(10.0);
+        stack.set_spacing(5.0);
+        stack.set_align(Align::Stretch);
+        stack.add(Item::fixed(20.0));
+        stack.add(Item::fixed(30.0));
+
+        let placed = stack.layout(Bounds::new(0.0, 100.0, 200.0, 400.0));
+        assert_eq!(placed[0], Bounds::new(10.0, 110.0, 180.0, 20.0));
+        assert_eq!(placed[1], Bounds::new(10.0, 135.0, 180.0, 30.0));
+    }
+
+    #[test]
+    fn test_items_shrink_and_grow_within_limits() {
+        let mut stack = Stack::new(Direction::Horizontal);
+        stack.add(Item::fixed(50.0));
+        stack.add(Item::shrinkable(100.0, 20.0));
+        stack.add(Item {
+            grow: 1.0,
+            max: 60.0,
+            ..Item::shrinkable(40.0, 40.0)
+        });
+
+        // 40 too wide, only the middle one can give anything up
+        let placed = stack.layout(Bounds::new(0.0, 0.0, 150.0, 10.0));
+        let widths: Vec<f32> = placed.iter().map(|b| b.width).collect();
+        assert_eq!(widths, vec![50.0, 60.0, 40.0]);
+        assert_eq!(placed[2].x, 110.0);
+
+        // Never below the minimum, even when that overflows
+        let placed = stack.layout(Bounds::new(0.0, 0.0, 50.0, 10.0));
+        assert_eq!(placed[1].width, 20.0);
+
+        // Spare room only goes to the growing item and stops at its maximum
+        let placed = stack.layout(Bounds::new(0.0, 0.0, 400.0, 10.0));
+        let widths: Vec<f32> = placed.iter().map(|b| b.width).collect();
+        assert_eq!(widths, vec![50.0, 100.0, 60.0]);
+    }
+
+    #[test]
+    fn test_items_align_across_the_stack() {
+        let mut stack = Stack::new(Direction::Horizontal);
+        stack.add(Item {
+            cross: Some(10.0),
+            ..Item::fixed(30.0)
+        });
+        stack.add(Item::fixed(30.0));
+        let bounds = Bounds::new(0.0, 0.0, 100.0, 40.0);
+
+        let placed = stack.layout(bounds);
+        assert_eq!((placed[0].y, placed[0].height), (0.0, 10.0));
+        assert_eq!((placed[1].y, placed[1].height), (0.0, 40.0));
+
+        stack.set_align(Align::Center);
+        assert_eq!(stack.layout(bounds)[0].y, 15.0);
+        stack.set_align(Align::Stretch);
+        assert_eq!(stack.layout(bounds)[0].height, 40.0);
+    }
+
+    #[test]
+    fn test_narrow_bounds_give_empty_items() {
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_padding(10.0);
+        stack.set_align(Align::Stretch);
+        stack.add(Item::shrinkable(20.0, 0.0));
+        stack.add(Item {
+            cross: Some(30.0),
+            ..Item::shrinkable(20.0, 0.0)
+        });
+
+        // Narrower than the padding, and a width gone negative as in a tall window
+        for width in [5.0, -40.0] {
+            for placed in stack.layout(Bounds::new(0.0, 0.0, width, -10.0)) {
+                assert_eq!((placed.width, placed.height), (0.0, 0.0));
+            }
+        }
+    }
+}
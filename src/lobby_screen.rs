@@ -0,0 +1,178 @@
+use tiny_skia::{Color, Pixmap};
+
+use crate::action::ScreenAction;
+use crate::drawable::Drawable;
+use crate::game_controls::{Button, TextField, draw_text};
+use crate::layout::{Bounds, Direction, Item, Stack};
+use crate::net::DEFAULT_PORT;
+
+// Screen for hosting or joining a game over the network
+#[derive(Debug, Clone)]
+pub struct LobbyScreen {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    name: TextField,
+    address: TextField,
+    status: String,
+    buttons: Vec<Button<ScreenAction>>,
+}
+
+impl LobbyScreen {
+    pub fn new(name: &str, status: String, connected: bool) -> Self {
+        // Leave replaces Host and Join while a session is running
+        let buttons = if connected {
+            vec![
+                Button::new("Leave".to_string(), 0xCC0000FF, ScreenAction::Leave),
+                Button::new("Back".to_string(), 0x555555FF, ScreenAction::Cancel),
+            ]
+        } else {
+            vec![
+                Button::new("Host".to_string(), 0x00AA00FF, ScreenAction::Host),
+                Button::new("Join".to_string(), 0x0000CCFF, ScreenAction::Join),
+                Button::new("Back".to_string(), 0x555555FF, ScreenAction::Cancel),
+            ]
+        };
+
+        LobbyScreen {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            name: TextField::new("Your name".to_string(), name.to_string(), 16),
+            address: TextField::new(
+                "Host address".to_string(),
+                format!("127.0.0.1:{}", DEFAULT_PORT),
+                40,
+            ),
+            status,
+            buttons,
+        }
+    }
+
+    pub fn configure(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+
+        let row_height = height / 18.0;
+        let row_x = x + width * 0.1;
+        let row_width = width * 0.8;
+
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_spacing(row_height * 0.25);
+        let name = stack.add(Item::fixed(row_height));
+        let address = stack.add(Item::fixed(row_height));
+        stack.add(Item::fixed(row_height * 1.625)); // Room for the status line
+        let button_row = stack.add(Item::fixed(row_height));
+        let placed = stack.layout(Bounds::new(
+            row_x,
+            y + row_height * 2.0,
+            row_width,
+            height - row_height * 2.0,
+        ));
+        let row = placed[name];
+        self.name.set_bounds(row.x, row.y, row.width, row.height);
+        let row = placed[address];
+        self.address.set_bounds(row.x, row.y, row.width, row.height);
+
+        let mut row = Stack::new(Direction::Horizontal);
+        row.set_spacing(row_width * 0.05);
+        for _ in &self.buttons {
+            row.add(Item::fixed(row_width * 0.3));
+        }
+        let placed = row.layout(placed[button_row]);
+        for (button, bounds) in self.buttons.iter_mut().zip(placed) {
+            button.set_bounds(bounds);
+        }
+    }
+
+    // Returns what the clicked button does (Host, Join, Leave or Cancel), if any
+    pub fn onclick(&mut self, x: f64, y: f64) -> Option<ScreenAction> {
+        self.name.onclick(x, y);
+        self.address.onclick(x, y);
+
+        for button in &self.buttons {
+            if button.contains_point(x, y) {
+                return Some(button.action);
+            }
+        }
+        None
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        for field in [&mut self.name, &mut self.address] {
+            if field.focused {
+                field.insert_text(text);
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        for field in [&mut self.name, &mut self.address] {
+            if field.focused {
+                field.backspace();
+            }
+        }
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.text.trim()
+    }
+
+    // The address as typed, with the default port added if none was given
+    pub fn address(&self) -> String {
+        let address = self.address.text.trim();
+        if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        }
+    }
+
+    // Port to host on, taken from the address field
+    pub fn port(&self) -> u16 {
+        self.address()
+            .rsplit(':')
+            .next()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT)
+    }
+}
+
+impl Drawable for LobbyScreen {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        pixmap.fill(Color::from_rgba8(235, 235, 235, 255));
+
+        let row_height = self.height / 18.0;
+        draw_text(
+            pixmap,
+            "Network game",
+            self.x + self.width * 0.1,
+            self.y + row_height * 1.4,
+            row_height,
+            0x000000FF,
+        );
+
+        self.name.draw(pixmap);
+        self.address.draw(pixmap);
+        draw_text(
+            pixmap,
+            &self.status,
+            self.x + self.width * 0.1,
+            self.y + row_height * (2.0 + 1.25 * 2.0 + 0.8),
+            row_height * 0.55,
+            0x333333FF,
+        );
+
+        for button in &self.buttons {
+            button.draw(pixmap);
+        }
+    }
+}
@@ -1,39 +1,127 @@
+pub mod action;
+pub mod ai;
+pub mod analysis;
 pub mod assets;
+pub mod cli;
 pub mod drawable;
+pub mod fair_dice;
 pub mod game_board;
 pub mod game_controls;
 pub mod game_state;
+pub mod heatmap;
+pub mod keymap;
+pub mod layout;
+pub mod lobby_screen;
+pub mod miner;
+pub mod net;
 pub mod objects;
+pub mod players_screen;
+pub mod preferences;
+pub mod render;
+pub mod settings_screen;
+pub mod simulation;
 pub mod vdf;
 
 // Re-export commonly used items for convenience
 pub use crate::game_board::GameBoard;
 //pub use assets::*;
+pub use action::{Action, ScreenAction};
 pub use drawable::Drawable;
 pub use game_controls::{Button, GameControls};
-pub use game_state::GameState;
-pub use objects::{Arrow, GameSettings, GameSquare, Png, User};
-use std::cmp;
+pub use game_state::{GameEvent, GameState};
+pub use keymap::{KEYMAP_FILE, Keymap};
+pub use lobby_screen::LobbyScreen;
+pub use miner::{CHECKPOINT_FILE, Miner, SearchEvent};
+pub use net::{Client, ClientMessage, Host, ServerMessage, Session};
+pub use objects::{
+    Arrow, BoardProfile, BoardSeed, Bot, Controller, Difficulty, GameSettings, GameSquare,
+    Overshoot, PlayerProfile, Png, RuleSet, SquareEffect, User,
+};
+pub use players_screen::PlayersScreen;
+pub use preferences::{PREFERENCES_FILE, Preferences};
+pub use settings_screen::SettingsScreen;
+pub use vdf::{DIG_ROUND, VdfProof};
 
 //use crate::{Arrow, GameSettings, GameSquare, GameState, User};
 //use ab_glyph::{Font, FontArc, Glyph, PxScale};
 ///use ab_glyph::FontArc;
+use curv::BigInt;
 use softbuffer::{Context, Surface};
 //use std::fs;
 use std::num::NonZeroU32;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::thread::{self, JoinHandle};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 //use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
-use curv::BigInt;
 // use std::time::Duration;
 use tiny_skia::{Color, Pixmap};
 use winit::application::ApplicationHandler;
 use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
-use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
-use winit::keyboard::{Key, NamedKey};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
 use winit::window::{Window, WindowAttributes, WindowId};
+
+// How long a computer player waits before taking its turn, so moves can be followed
+const AI_TURN_DELAY: Duration = Duration::from_millis(800);
+// How often a network game checks for messages
+const NET_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Where the Save and Load shortcuts keep the game
+const SAVE_FILE: &str = "savegame.json";
+// Moves that can be taken back with Undo
+const UNDO_LIMIT: usize = 50;
+
+// Events sent to the event loop from other threads
+#[derive(Debug, Clone, Copy)]
+enum AppEvent {
+    Mining, // The mining thread has news in its channel
+    Seeded, // The seed for the next board is ready
+}
+
+// Full window screens that are shown instead of the board while open
+enum Screen {
+    Settings(Box<SettingsScreen>),
+    Players(PlayersScreen),
+    Lobby(LobbyScreen),
+}
+
+impl Screen {
+    fn configure(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        match self {
+            Screen::Settings(screen) => screen.configure(x, y, width, height),
+            Screen::Players(screen) => screen.configure(x, y, width, height),
+            Screen::Lobby(screen) => screen.configure(x, y, width, height),
+        }
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        match self {
+            Screen::Settings(screen) => screen.insert_text(text),
+            Screen::Players(screen) => screen.insert_text(text),
+            Screen::Lobby(screen) => screen.insert_text(text),
+        }
+    }
+
+    fn backspace(&mut self) {
+        match self {
+            Screen::Settings(screen) => screen.backspace(),
+            Screen::Players(screen) => screen.backspace(),
+            Screen::Lobby(screen) => screen.backspace(),
+        }
+    }
+}
+
+impl Drawable for Screen {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        match self {
+            Screen::Settings(screen) => screen.draw(pixmap),
+            Screen::Players(screen) => screen.draw(pixmap),
+            Screen::Lobby(screen) => screen.draw(pixmap),
+        }
+    }
+}
+
 struct App {
     window: Option<Arc<Window>>,
     surface: Option<Surface<Arc<Window>, Arc<Window>>>,
@@ -42,254 +130,535 @@ struct App {
     game_state: GameState,
     game_board: GameBoard,
     game_controls: GameControls,
+    screen: Option<Screen>,
+    ai_turn_at: Option<Instant>, // When the waiting computer player moves
+    session: Option<Session>,    // Set while hosting or playing a network game
     first_called: bool,
-    mining_thread: Option<JoinHandle<()>>,
-    mining_stop_signal: Option<Arc<AtomicBool>>,
-    is_mining: bool,
+    miner: Miner,
+    mining_player: usize, // Seat that gets the rewards for what is found
+    keymap: Keymap,
+    modifiers: ModifiersState,
+    undo: Vec<GameState>, // Games from before each local move, newest last
+    proxy: EventLoopProxy<AppEvent>,
+    seeding: Option<Receiver<(BigInt, Option<VdfProof>)>>, // Seed being worked out for the next board
 }
 
 impl App {
-    fn new() -> Self {
+    // The proxy wakes the event loop when the mining or seeding threads have news
+    fn new(proxy: EventLoopProxy<AppEvent>) -> Self {
+        let mut game_state = GameState::new();
+
+        // Players from the last session, or ask for names on the first run
+        let screen = match Preferences::load_from_file(PREFERENCES_FILE) {
+            Ok(preferences) if !preferences.players.is_empty() => {
+                let mut settings = game_state.settings.clone();
+                settings.players = preferences.players;
+                game_state.update_settings(settings);
+                game_state.reset();
+                None
+            }
+            _ => Some(Screen::Players(PlayersScreen::new(
+                &game_state.settings.players,
+            ))),
+        };
+
         Self {
             window: None,
             surface: None,
             context: None,
             cursor_position: (0.0, 0.0),
-            game_state: GameState::new(),
+            game_state,
             game_board: GameBoard::SquareBoard {
                 squares: vec![],
                 arrows: vec![],
             },
             game_controls: GameControls::new(),
+            screen,
+            ai_turn_at: None,
+            session: None,
             first_called: false,
-            mining_thread: None,
-            mining_stop_signal: None,
-            is_mining: false,
+            miner: Miner::new(Some(PathBuf::from(CHECKPOINT_FILE)), {
+                let proxy = proxy.clone();
+                move || {
+                    let _ = proxy.send_event(AppEvent::Mining);
+                }
+            }),
+            mining_player: 0,
+            keymap: Keymap::load_or_create(KEYMAP_FILE),
+            modifiers: ModifiersState::empty(),
+            undo: Vec::new(),
+            proxy,
+            seeding: None,
         }
     }
 
+    // Starts a new board. A seed that takes a while is worked out on its own thread and
+    // the board changes once it is ready, play carries on meanwhile.
+    fn new_board(&mut self) {
+        if !self.game_state.settings.board_seed.is_slow() {
+            self.seeding = None;
+            self.game_state.reset();
+            // Rebuild the board so the new arrows and special squares are shown
+            self.game_board.reset();
+            return;
+        }
+
+        // A newer request replaces the receiver, so an older seed is never used
+        let (sender, receiver) = mpsc::channel();
+        self.seeding = Some(receiver);
+        let settings = self.game_state.settings.clone();
+        let proxy = self.proxy.clone();
+        thread::spawn(move || {
+            if sender.send(GameState::new_seed(&settings)).is_ok() {
+                let _ = proxy.send_event(AppEvent::Seeded);
+            }
+        });
+        println!("Working out the seed for the next board");
+    }
+
+    fn receive_seed(&mut self) {
+        let Some((seed, proof)) = self
+            .seeding
+            .as_ref()
+            .and_then(|seeding| seeding.try_recv().ok())
+        else {
+            return;
+        };
+        self.seeding = None;
+        self.game_state.reset_with_seed(seed, proof);
+        self.game_board.reset();
+    }
+
     fn take_a_turn(&mut self) {
         if self.first_called {
             // If first_called is true, we are already in the middle of a turn
             return;
         }
-        // Player 0 spins immediately
         self.first_called = true; // Set to true to indicate a turn is in progress
-                                  //println!("Taking a turn...player 1");
-        self.game_state.spin(0);
-
-        // Player 1 spins after delay
-        //println!("Taking a turn...player 2");
-        self.game_state.spin(1);
+        self.game_state.take_turn();
         self.first_called = false; // Set to true to indicate a turn is in progress
     }
 
-    #[allow(unused_variables)]
-    fn get_sq_center(board: &GameBoard, sq_number: usize) -> Option<(f32, f32)> {
-        if let GameBoard::SquareBoard { squares, arrows } = board {
-            if let Some(square) = squares.get(sq_number) {
-                let center = square.center();
-                return Some(center);
+    // Starts the delay before a computer player's turn, or cancels it if a human is up
+    fn schedule_ai_turn(&mut self) {
+        // In a network game the host plays the computer players
+        if self.screen.is_some() || self.is_client() || !self.game_state.ai_to_move() {
+            self.ai_turn_at = None;
+        } else if self.ai_turn_at.is_none() {
+            self.ai_turn_at = Some(Instant::now() + AI_TURN_DELAY);
+        }
+    }
+
+    fn is_client(&self) -> bool {
+        matches!(self.session, Some(Session::Client(_)))
+    }
+
+    // True when the current player is played from this window
+    fn local_turn(&self) -> bool {
+        let current = self.game_state.current_player;
+        match &self.session {
+            Some(Session::Client(client)) => client.seat == Some(current),
+            Some(Session::Host(host)) => {
+                !host.remote_seats().contains(&current) && !self.game_state.ai_to_move()
             }
+            None => !self.game_state.ai_to_move(),
         }
-        None
+    }
+
+    // A client asks the host to roll, a host starts a dice round with the other players
+    fn roll(&mut self) {
+        match &mut self.session {
+            Some(Session::Client(client)) => client.send(&ClientMessage::Roll),
+            Some(Session::Host(host)) => host.request_roll(&self.game_state),
+            None => self.take_a_turn(),
+        }
+    }
+
+    fn use_reroll(&mut self) {
+        match &mut self.session {
+            Some(Session::Client(client)) => client.send(&ClientMessage::UseReroll),
+            Some(Session::Host(host)) => host.use_reroll(&mut self.game_state),
+            None => {
+                self.game_state.use_reroll();
+            }
+        }
+    }
+
+    fn choose_die(&mut self, value: u32) {
+        match &mut self.session {
+            Some(Session::Client(client)) => client.send(&ClientMessage::ChooseDie(value)),
+            Some(Session::Host(host)) => host.choose_die(&mut self.game_state, value),
+            None => self.game_state.choose_die(value),
+        }
+    }
+
+    // Exchanges messages with the other windows, returns true if the game may have changed
+    fn poll_network(&mut self) -> bool {
+        match &mut self.session {
+            Some(Session::Host(host)) => {
+                let changed = host.poll(&mut self.game_state);
+                host.sync(&self.game_state);
+                changed
+            }
+            Some(Session::Client(client)) => {
+                let messages = client.poll();
+                let changed = !messages.is_empty();
+                for message in messages {
+                    match message {
+                        ServerMessage::Welcome { seat } => {
+                            println!("Joined as player {}", seat + 1);
+                        }
+                        ServerMessage::Rejected(reason) => {
+                            println!("Could not join: {}", reason);
+                            self.session = None;
+                            break;
+                        }
+                        ServerMessage::Full(state) => {
+                            // A VDF seeded board can be checked instead of trusting the host
+                            if state.settings.seed_proof.is_some()
+                                && let Err(e) = state.verify_board()
+                            {
+                                println!("The host's board does not verify: {}", e);
+                            }
+                            self.game_state = *state;
+                            self.game_board.reset();
+                        }
+                        ServerMessage::Diff(diff) => diff.apply(&mut self.game_state),
+                        ServerMessage::TurnResult { action, diff } => {
+                            client.apply_turn(&mut self.game_state, &action, &diff);
+                        }
+                        // Dice rounds are answered by the client itself
+                        ServerMessage::RollRequest { .. }
+                        | ServerMessage::Commitments { .. }
+                        | ServerMessage::RollAborted { .. } => {}
+                    }
+                }
+                changed
+            }
+            None => false,
+        }
+    }
+
+    fn open_lobby(&mut self) {
+        let name = self
+            .game_state
+            .settings
+            .players
+            .first()
+            .map(|profile| profile.name.clone())
+            .unwrap_or_default();
+        let status = match &self.session {
+            Some(session) => session.status(),
+            None => "Not connected".to_string(),
+        };
+        self.screen = Some(Screen::Lobby(LobbyScreen::new(
+            &name,
+            status,
+            self.session.is_some(),
+        )));
     }
 
     fn draw(&mut self) {
+        let local_turn = self.local_turn();
+
         if let (Some(window), Some(surface)) = (&self.window, &mut self.surface) {
             // Get the surface buffer and create a pixmap
             let mut buffer = surface.buffer_mut().unwrap();
             let size = window.inner_size();
             let (width, height) = (size.width, size.height);
 
-            let mut pixmap = Pixmap::new(width, height).unwrap();
-
-            // Clear the pixmap with a white background
-            pixmap.fill(Color::from_rgba8(255, 255, 255, 255));
-
-            // Draw a 10x10 grid of squares
-            let board_size = cmp::min(width, height) as f32 * 0.9; // 80% of the smaller dimension
-            let board_padding = cmp::min(width, height) as f32 * 0.1; // 5%
-                                                                      //let grid_count = 10.0;
-            let grid_count = self.game_state.grid_size as f32;
-            let spacing = (board_size / grid_count) as f32 * 0.1;
-            let sq_size = (board_size / grid_count) - (spacing * 2.0);
-
-            // Set grid height to 80% of window height
-            //let grid_height = height as f32 * 0.8;
-            //let square_scale = grid_height / (grid_size as f32 * 2.5); // Scale squares to fit
-            //let spacing = grid_height / grid_size as f32; // Spacing based on grid height
-
-            // Calculate offsets to center the grid
-            //let grid_width = spacing * grid_size as f32;
-            //let offset_x_start = (width as f32 - grid_width) / 2.0;
-            //let offset_y_start = (height as f32 - grid_height) / 2.0;
-
-            self.game_board.init(
-                //let game_board =  self.game_board.new(
-                board_padding as i32,
-                board_size as i32,
-                grid_count as i32,
-                spacing as i32,
-                self.game_state.colors.clone(),
-                self.game_state.arrows.as_mut(),
-            );
+            // An open screen replaces the board
+            if let Some(screen) = &mut self.screen {
+                let mut pixmap = Pixmap::new(width, height).unwrap();
 
-            let button_list = vec![
-                Button::new("Roll".to_string(), 0x00CC00FF),
-                Button::new("Dig".to_string(), 0xCC0000FF),
-                Button::new("Reset".to_string(), 0x0000CCFF),
-            ];
-
-            self.game_controls.configure(
-                //cmp::max(width, height) as f32 + board_padding,
-                board_size + board_padding,
-                board_padding,
-                cmp::min(
-                    (width as f32 - board_size - (board_padding * 2.0)) as i32,
-                    (sq_size * 3.0) as i32,
-                ) as f32,
-                height as f32 - (board_padding * 2.0),
-                0xCCCCCC0F,
-                "The Game".to_string(),
-                (sq_size / 2.0) as f32,
-                button_list,
-            );
+                // Clear the pixmap with a white background
+                pixmap.fill(Color::from_rgba8(255, 255, 255, 255));
 
-            // Draw the game board
-            self.game_board.draw(&mut pixmap);
-            self.game_controls.draw(&mut pixmap);
-
-            // Copy pixmap to softbuffer
-            for (i, pixel) in pixmap.pixels().iter().enumerate() {
-                let r = pixel.red();
-                let g = pixel.green();
-                let b = pixel.blue();
-                let a = pixel.alpha();
-                buffer[i] =
-                    ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
-            }
-
-            for player_num in 0..self.game_state.users.len() {
-                //let player = Png::new();
-                let mut player_position = Self::get_sq_center(
-                    &self.game_board,
-                    (self.game_state.users[player_num].position - 1) as usize,
-                )
-                .unwrap_or((0.0, 0.0));
-
-                let player = Png::new(player_num as i32, player_num as usize);
-
-                // Position players to the left of the start square
-                if self.game_state.new_game {
-                    player_position.0 =
-                        player_position.0 - sq_size + ((player_num % 2) as f32 * (sq_size / 5.0));
-                    player_position.1 =
-                        player_position.1 - ((player_num % 2) as f32 * (sq_size / 4.0));
-                }
-
-                player.draw_png_scaled_height(
-                    &mut buffer,
-                    width as u32,
-                    (player_position.0 - sq_size / 2.0) as i32,
-                    (player_position.1 - sq_size / 2.0) as i32,
-                    (sq_size * 0.9) as u32,
-                    !get_range_flag(
-                        self.game_state.users[player_num].position,
-                        grid_count as u32,
-                    ),
-                );
+                screen.configure(0.0, 0.0, width as f32, height as f32);
+                screen.draw(&mut pixmap);
+                render::copy_pixmap_to_buffer(&pixmap, &mut buffer);
+                buffer.present().unwrap();
+                return;
             }
 
+            let button_list = render::control_buttons(
+                &self.game_state,
+                &self.keymap,
+                local_turn,
+                self.miner.is_running(),
+            );
+            let frame = match render::draw_game(
+                &mut self.game_state,
+                &mut self.game_board,
+                &mut self.game_controls,
+                button_list,
+                width,
+                height,
+            ) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    println!("Nothing drawn: {}", e);
+                    return;
+                }
+            };
+            buffer.copy_from_slice(&frame);
+
             // Present the buffer
             buffer.present().unwrap();
         }
     }
 
     fn start_mining(&mut self) {
-        if self.is_mining {
+        if self.miner.is_running() {
             return; // Already mining
         }
 
-        // Create stop signal
-        let stop_signal = Arc::new(AtomicBool::new(false));
-        let stop_signal_clone = stop_signal.clone();
-
-        // Spawn the mining thread
-        let handle = thread::spawn(move || {
-            println!("=== VDF Property Checker ===");
-            let x = BigInt::from(42);
-            println!("Input x: {}", x);
-
-            let a_b_delta = vdf::custom_setup(&x);
-            println!("Discriminant: {}", a_b_delta.delta);
-            println!("Initial form: a={}, b={}", a_b_delta.a, a_b_delta.b);
-
-            println!("\n=== Search Phase ===");
-
-            // Pass the stop signal to the VDF search function
-            // You'll need to modify vdf::start_search to accept a stop signal
-            let rx: Receiver<BigInt> =
-                vdf::start_search_with_stop(a_b_delta, stop_signal_clone.clone());
+        let config = self.game_state.settings.vdf.clone();
+        println!(
+            "Mining from \"{}\" in a {} class group, {} squarings per proof",
+            config.input, config.discriminant, DIG_ROUND
+        );
+        let from = self.game_state.dig_ledger.head(&config);
+        self.miner.start(config, DIG_ROUND, from);
+
+        // Whoever is at the window digs, a client for its own seat
+        self.mining_player = match &self.session {
+            Some(Session::Client(client)) => client.seat.unwrap_or(0),
+            _ if self.local_turn() => self.game_state.current_player,
+            _ => 0,
+        };
+        self.game_controls.mining.start();
+    }
 
-            // Process results until stopped or completed
-            while !stop_signal_clone.load(Ordering::Relaxed) {
-                match rx.recv_timeout(std::time::Duration::from_millis(100)) {
-                    Ok(iteration) => {
-                        println!("Found at iteration {}: divisible by 1000", iteration);
-                    }
-                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
-                        // Continue checking for stop signal
-                        continue;
-                    }
-                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
-                        println!("VDF search completed or disconnected");
-                        break;
+    // Shows what the mining thread sent since the last wake-up
+    fn receive_mining_events(&mut self) {
+        for event in self.miner.events() {
+            self.game_controls.mining.update(&event);
+            let proof = match event {
+                SearchEvent::Found(proof) => {
+                    println!(
+                        "Proof {} found: y = ({}, {}, {})",
+                        self.game_controls.mining.found, proof.y.a, proof.y.b, proof.y.c
+                    );
+                    proof
+                }
+                SearchEvent::Missed(proof) => proof,
+                _ => continue,
+            };
+
+            // Every round is handed in to keep the chain going, the host checks a
+            // client's before handing out the reward
+            match &self.session {
+                Some(Session::Client(client)) => {
+                    client.send(&ClientMessage::Found(Box::new(proof)));
+                }
+                _ => {
+                    if let Err(e) = self.game_state.award_find(self.mining_player, &proof) {
+                        println!("No reward: {}", e);
                     }
                 }
             }
-
-            if stop_signal_clone.load(Ordering::Relaxed) {
-                println!("Mining stopped by user request");
-            }
-        });
-
-        self.mining_thread = Some(handle);
-        self.mining_stop_signal = Some(stop_signal);
-        self.is_mining = true;
-        println!("Mining started!");
+        }
     }
 
+    // Returns straight away, the worker finishes its current squaring on its own
     fn stop_mining(&mut self) {
-        if !self.is_mining {
-            return; // Not currently mining
+        self.miner.stop();
+        self.game_controls.mining.stop();
+        println!("Mining stopped!");
+    }
+
+    // Does what a button or a keyboard shortcut asks for
+    fn perform(&mut self, action: Action, event_loop: &ActiveEventLoop) {
+        if action.host_only() && self.is_client() {
+            println!("Only the host can change the game");
+            return;
         }
 
-        // Signal the thread to stop
-        if let Some(stop_signal) = &self.mining_stop_signal {
-            stop_signal.store(true, Ordering::Relaxed);
+        match action {
+            // Computer and remote players roll for themselves
+            Action::Roll if self.local_turn() => {
+                self.save_undo();
+                self.roll();
+            }
+            Action::Reroll if self.local_turn() => {
+                self.save_undo();
+                self.use_reroll();
+            }
+            Action::ChooseDie(value) if self.local_turn() => {
+                self.save_undo();
+                self.choose_die(value);
+            }
+            Action::Roll | Action::Reroll | Action::ChooseDie(_) => {}
+            Action::Reset => {
+                self.save_undo();
+                self.new_board();
+            }
+            Action::Difficulty => {
+                // Cycle the difficulty and start a new board with it
+                self.save_undo();
+                self.game_state.settings.difficulty = self.game_state.settings.difficulty.next();
+                self.new_board();
+            }
+            Action::Save => {
+                if let Err(e) = self.game_state.save_to_file(SAVE_FILE) {
+                    println!("Could not save the game: {}", e);
+                }
+            }
+            Action::Load => match GameState::load_from_file(SAVE_FILE) {
+                Ok(state) => {
+                    self.save_undo();
+                    self.seeding = None;
+                    self.game_state = state;
+                    self.game_board.reset();
+                }
+                Err(e) => println!("Could not load {}: {}", SAVE_FILE, e),
+            },
+            Action::Dig => self.toggle_mining(),
+            Action::Heatmap => {
+                let legend = &mut self.game_controls.heatmap;
+                legend.source = legend.source.next();
+            }
+            Action::Undo => self.undo(),
+            Action::Players => {
+                self.screen = Some(Screen::Players(PlayersScreen::new(
+                    &self.game_state.settings.players,
+                )));
+            }
+            Action::Settings => {
+                self.screen = Some(Screen::Settings(Box::new(SettingsScreen::new(
+                    &self.game_state.settings,
+                ))));
+            }
+            Action::Network => self.open_lobby(),
+            Action::Quit => event_loop.exit(),
         }
+    }
 
-        // Wait for the thread to finish
-        if let Some(handle) = self.mining_thread.take() {
-            let _ = handle.join(); // Ignore join errors
+    // Remembers the game before a local move so Undo can go back to it
+    fn save_undo(&mut self) {
+        if self.session.is_some() {
+            return; // The other players have already seen the move
+        }
+        self.undo.push(self.game_state.clone());
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
         }
+    }
 
-        self.mining_stop_signal = None;
-        self.is_mining = false;
-        println!("Mining stopped!");
+    // Goes back to before the last local move, computer turns since then are taken back too
+    fn undo(&mut self) {
+        if self.session.is_some() {
+            println!("Moves cannot be taken back in a network game");
+            return;
+        }
+        match self.undo.pop() {
+            Some(state) => {
+                self.seeding = None;
+                // Rebuild the board in case the move was a reset or a load
+                if state.arrows != self.game_state.arrows
+                    || state.special_squares != self.game_state.special_squares
+                    || state.grid_size != self.game_state.grid_size
+                {
+                    self.game_board.reset();
+                }
+                self.game_state = state;
+            }
+            None => println!("Nothing to undo"),
+        }
     }
 
     fn toggle_mining(&mut self) {
-        if self.is_mining {
+        if self.miner.is_running() {
             self.stop_mining();
         } else {
             self.start_mining();
         }
     }
+
+    fn screen_onclick(&mut self, x: f64, y: f64) {
+        match &mut self.screen {
+            Some(Screen::Settings(settings_screen)) => {
+                match settings_screen.onclick(x, y) {
+                    Some(ScreenAction::Apply) => {
+                        // New settings always start a new game
+                        let settings = settings_screen.to_settings();
+                        self.game_state.update_settings(settings);
+                        self.new_board();
+                        self.save_preferences();
+                        self.screen = None;
+                    }
+                    Some(ScreenAction::Cancel) => self.screen = None,
+                    _ => {}
+                }
+            }
+            Some(Screen::Players(players_screen)) => match players_screen.onclick(x, y) {
+                Some(ScreenAction::Apply) => {
+                    let players = players_screen.to_profiles();
+                    self.game_state.update_players(players);
+                    self.save_preferences();
+                    self.screen = None;
+                }
+                Some(ScreenAction::Cancel) => self.screen = None,
+                _ => {}
+            },
+            Some(Screen::Lobby(lobby_screen)) => match lobby_screen.onclick(x, y) {
+                Some(ScreenAction::Host) => match Host::start(lobby_screen.port()) {
+                    Ok(host) => {
+                        self.session = Some(Session::Host(Box::new(host)));
+                        self.screen = None;
+                    }
+                    Err(e) => lobby_screen.set_status(format!("Could not host: {}", e)),
+                },
+                Some(ScreenAction::Join) => {
+                    let name = lobby_screen.name().to_string();
+                    match Client::connect(&lobby_screen.address(), &name) {
+                        Ok(client) => {
+                            self.session = Some(Session::Client(client));
+                            self.screen = None;
+                        }
+                        Err(e) => lobby_screen.set_status(format!("Could not join: {}", e)),
+                    }
+                }
+                Some(ScreenAction::Leave) => {
+                    self.session = None;
+                    self.screen = None;
+                }
+                Some(ScreenAction::Cancel) => self.screen = None,
+                _ => {}
+            },
+            None => {}
+        }
+    }
+
+    fn screen_key_input(&mut self, event: &KeyEvent) {
+        let Some(screen) = &mut self.screen else {
+            return;
+        };
+
+        match &event.logical_key {
+            Key::Named(NamedKey::Escape) => self.screen = None,
+            Key::Named(NamedKey::Backspace) => screen.backspace(),
+            _ => {
+                if let Some(text) = &event.text {
+                    screen.insert_text(text);
+                }
+            }
+        }
+    }
+
+    // Remembers the player names and colours for the next session
+    fn save_preferences(&self) {
+        let preferences = Preferences {
+            players: self.game_state.settings.players.clone(),
+        };
+        if let Err(e) = preferences.save_to_file(PREFERENCES_FILE) {
+            println!("Could not save preferences: {}", e);
+        }
+    }
 }
 
-impl ApplicationHandler for App {
+impl ApplicationHandler<AppEvent> for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let window_attributes = WindowAttributes::default()
             .with_title("The dynamic of life game")
@@ -347,6 +716,31 @@ impl ApplicationHandler for App {
             WindowEvent::CursorMoved { position, .. } => {
                 self.cursor_position = (position.x, position.y);
                 //println!("Mouse moved to: x={:.2}, y={:.2}", position.x, position.y);
+                if self.screen.is_none()
+                    && self.game_controls.pointer_moved(position.x, position.y)
+                    && let Some(window) = &self.window
+                {
+                    window.request_redraw();
+                }
+            }
+
+            WindowEvent::CursorLeft { .. } => {
+                if self.game_controls.pointer_left()
+                    && let Some(window) = &self.window
+                {
+                    window.request_redraw();
+                }
+            }
+
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.screen.is_some() => {
+                self.screen_onclick(self.cursor_position.0, self.cursor_position.1);
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
             }
 
             WindowEvent::MouseInput {
@@ -364,84 +758,130 @@ impl ApplicationHandler for App {
                     None => {}
                 }
 
-                match &self
+                // Buttons show that they are held down and act when the mouse comes up
+                if self
                     .game_controls
-                    .onclick(self.cursor_position.0, self.cursor_position.1)
+                    .press(self.cursor_position.0, self.cursor_position.1)
+                    && let Some(window) = &self.window
                 {
-                    Some(button_name) => {
-                        match button_name.as_str() {
-                            "Roll" => {
-                                self.take_a_turn();
-                                //self.game_state.spin();
-                                /*
-                                for arrow in &self.game_state.arrows.clone() {
-                                    if &self.game_state.user_position == &arrow.0 {
-                                        println!(
-                                            "Landed at bottom of arrow on square {}. Moving to {}.",
-                                            arrow.0, arrow.1
-                                        );
-                                        self.game_state.move_player(arrow.1);
-                                    }
-                                }
-                                */
-                            }
-                            "Dig" => {
-                                self.toggle_mining();
-                            }
-                            "Reset" => {
-                                self.game_state.reset();
-                            }
-                            _ => {}
-                        }
-                        //println!("🎯 Clicked inside button: {}", button_name);
-
-                        if let Some(window) = &self.window {
-                            window.request_redraw();
-                        }
-                    }
-                    None => {}
+                    window.request_redraw();
                 }
             }
 
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        logical_key: Key::Named(NamedKey::Escape),
-                        state: ElementState::Pressed,
-                        ..
-                    },
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
                 ..
-            } => {
-                event_loop.exit();
+            } if self.screen.is_none() => {
+                if let Some(action) = self
+                    .game_controls
+                    .release(self.cursor_position.0, self.cursor_position.1)
+                {
+                    self.perform(action, event_loop);
+                }
+
+                // Redraw either way so a button let go of off it stops looking pressed
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+
+            WindowEvent::KeyboardInput { event, .. }
+                if self.screen.is_some() && event.state == ElementState::Pressed =>
+            {
+                self.screen_key_input(&event);
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                if let Some(action) = self.keymap.action_for(&event.logical_key, self.modifiers) {
+                    self.perform(action, event_loop);
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                }
             }
             _ => {}
         }
     }
-}
 
-// TODO: Make this generalized for any range not just a grid of 10
-/*
-fn get_range_flag(n: u32) -> bool {
-    if n < 1 {
-        return false;
-    } // Handle invalid input (optional)
-    let mod_value = (n - 1) % 20;
-    mod_value < 10
-}
-*/
-
-fn get_range_flag(n: u32, range_size: u32) -> bool {
-    if n < 1 || range_size < 1 {
-        return false;
-    } // Handle invalid input
-    let mod_value = (n - 1) % (2 * range_size);
-    mod_value < range_size
+    // Lets the mining worker finish before the window goes away
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        self.miner.shutdown();
+    }
+
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: AppEvent) {
+        match event {
+            AppEvent::Mining => {
+                self.receive_mining_events();
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+            AppEvent::Seeded => {
+                self.receive_seed();
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if self.poll_network() {
+            // Keep the lobby status current while it is open
+            if let (Some(Screen::Lobby(lobby_screen)), Some(session)) =
+                (&mut self.screen, &self.session)
+            {
+                lobby_screen.set_status(session.status());
+            }
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+        }
+
+        if self.ai_turn_at.is_some_and(|at| Instant::now() >= at) {
+            self.ai_turn_at = None;
+            if self.screen.is_none() && self.game_state.ai_to_move() {
+                self.roll();
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            }
+        }
+
+        // Sleep until the next computer turn or network poll, or until the next event
+        self.schedule_ai_turn();
+        let network_poll = self
+            .session
+            .as_ref()
+            .map(|_| Instant::now() + NET_POLL_INTERVAL);
+        match self.ai_turn_at.into_iter().chain(network_poll).min() {
+            Some(at) => event_loop.set_control_flow(ControlFlow::WaitUntil(at)),
+            None => event_loop.set_control_flow(ControlFlow::Wait),
+        }
+    }
 }
 
 fn main() {
-    let event_loop = EventLoop::new().unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        if let Err(e) = result {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::<AppEvent>::with_user_event().build().unwrap();
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let mut app = App::new();
+    let mut app = App::new(event_loop.create_proxy());
     event_loop.run_app(&mut app).unwrap();
 }
@@ -0,0 +1,488 @@
+// File: src/miner.rs
+// The worker thread behind "Dig". One thread is started with the window and kept for
+// its lifetime, each Dig hands it a new run and stopping only raises a flag, so the
+// window never waits for the VDF to notice. A run saves where it got to now and then,
+// so a stopped run carries on from there on the next Dig.
+use crate::vdf::{self, VdfConfig, VdfProof, form_serde};
+use class_group::BinaryQF;
+use curv::BigInt;
+use hmac::{Hmac, Mac, NewMac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// How often a run reports progress, in squarings
+const PROGRESS_INTERVAL: u64 = 64;
+// How often a run saves a checkpoint, in squarings
+const CHECKPOINT_INTERVAL: u64 = 256;
+
+pub const CHECKPOINT_FILE: &str = "vdf_checkpoint.json";
+
+// Messages from a running search
+#[derive(Debug, Clone)]
+pub enum SearchEvent {
+    Progress(u64), // Squarings done since the run started
+    Found(VdfProof),
+    Missed(VdfProof), // A round whose output misses the target, it still carries the chain on
+    Resumed(u64),     // Carrying on from a checkpoint this many squarings in
+}
+
+// Where a run got to, enough to carry on with the round in progress
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub config: VdfConfig,
+    pub t: u64,
+    pub x: BigInt, // Input of the round in progress
+    #[serde(with = "form_serde")]
+    pub y: BinaryQF,
+    pub iteration: u64, // Squarings done in the round, y = g^(2^iteration)
+    pub done: u64,      // Squarings done since the run first started
+}
+
+// A checkpoint with an HMAC over it, keyed with a secret kept next to the file
+#[derive(Serialize, Deserialize)]
+struct SealedCheckpoint {
+    checkpoint: Checkpoint,
+    mac: [u8; 32],
+}
+
+impl Checkpoint {
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let sealed = SealedCheckpoint {
+            checkpoint: self.clone(),
+            mac: self.mac(&checkpoint_key(path)?)?,
+        };
+        let json_string = serde_json::to_string_pretty(&sealed)?;
+        let mut file = fs::File::create(path)?;
+        file.write_all(json_string.as_bytes())?;
+        Ok(())
+    }
+
+    // Fails if the file was changed by anything but save_to_file
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_string = fs::read_to_string(path)?;
+        let sealed: SealedCheckpoint = serde_json::from_str(&json_string)?;
+        let checkpoint = sealed.checkpoint;
+        if checkpoint.mac(&checkpoint_key(path)?)? != sealed.mac {
+            return Err("the checkpoint does not match its MAC".into());
+        }
+        if checkpoint.iteration > checkpoint.t {
+            return Err("the checkpoint is past the end of its round".into());
+        }
+        Ok(checkpoint)
+    }
+
+    fn mac(&self, key: &[u8; 32]) -> Result<[u8; 32], serde_json::Error> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(&serde_json::to_vec(self)?);
+        Ok(mac.finalize().into_bytes().into())
+    }
+
+    // True when the checkpoint belongs to a run of this search in the round from x
+    fn fits(&self, config: &VdfConfig, t: u64, disc: &BigInt, x: &BigInt) -> bool {
+        self.config == *config && self.t == t && self.y.discriminant() == *disc && self.x == *x
+    }
+}
+
+// The key sits beside the checkpoint with a .key extension, made on first use
+fn checkpoint_key(path: &Path) -> std::io::Result<[u8; 32]> {
+    let key_path = path.with_extension("key");
+    if let Ok(bytes) = fs::read(&key_path)
+        && let Ok(key) = <[u8; 32]>::try_from(bytes.as_slice())
+    {
+        return Ok(key);
+    }
+
+    let mut key = [0u8; 32];
+    rand::rng().fill_bytes(&mut key);
+    fs::write(&key_path, key)?;
+    Ok(key)
+}
+
+enum Command {
+    Start {
+        config: VdfConfig,
+        t: u64,
+        from: BigInt,
+        stop: Arc<AtomicBool>,
+    },
+    Quit,
+}
+
+pub struct Miner {
+    commands: Sender<Command>,
+    events: Receiver<SearchEvent>,
+    stop: Option<Arc<AtomicBool>>, // Flag of the run in progress
+    handle: Option<JoinHandle<()>>,
+}
+
+// Where the worker keeps its checkpoint, None to never save one
+type CheckpointPath = Option<PathBuf>;
+
+impl Miner {
+    // notify is called from the worker after every event, to wake up whoever reads them
+    pub fn new(checkpoint: CheckpointPath, notify: impl Fn() + Send + 'static) -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let handle = thread::spawn(move || run_worker(command_rx, event_tx, checkpoint, notify));
+
+        Miner {
+            commands,
+            events,
+            stop: None,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    // Starts a search with rounds of t squarings from input from, any run in progress
+    // is stopped first. A checkpoint is only picked up if it is for a round from there.
+    pub fn start(&mut self, config: VdfConfig, t: u64, from: BigInt) {
+        self.stop();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = Some(stop.clone());
+        // The worker picks the run up as soon as the previous one has wound down
+        let _ = self.commands.send(Command::Start {
+            config,
+            t,
+            from,
+            stop,
+        });
+    }
+
+    // Asks the run to stop and returns straight away
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Events sent since the last call, events of a stopped run may still trail in
+    pub fn events(&self) -> Vec<SearchEvent> {
+        self.events.try_iter().collect()
+    }
+
+    // Stops the run and waits for the worker to exit, for when the window closes
+    pub fn shutdown(&mut self) {
+        self.stop();
+        let _ = self.commands.send(Command::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run_worker(
+    commands: Receiver<Command>,
+    events: Sender<SearchEvent>,
+    checkpoint: CheckpointPath,
+    notify: impl Fn(),
+) {
+    let send = |event: SearchEvent| {
+        // Nobody listening only happens while shutting down
+        if events.send(event).is_ok() {
+            notify();
+        }
+    };
+
+    while let Ok(Command::Start {
+        config,
+        t,
+        from,
+        stop,
+    }) = commands.recv()
+    {
+        search(&config, t, from, checkpoint.as_deref(), &stop, &send);
+    }
+}
+
+// Runs the VDF in rounds of t squarings until stop is set. Each round proves one
+// output and the next round starts from a hash of it, so the results form a chain
+// anyone can check from the first input. Every round is sent on, as a find when its
+// output meets the target.
+fn search(
+    config: &VdfConfig,
+    t: u64,
+    from: BigInt,
+    path: Option<&Path>,
+    stop: &AtomicBool,
+    send: &impl Fn(SearchEvent),
+) {
+    // A generated discriminant can take a moment, so it is worked out here and
+    // given up on as soon as the run is stopped
+    let Some(disc) = config.discriminant_until(stop) else {
+        println!("VDF search stopped");
+        return;
+    };
+    let throttle = Duration::from_millis(config.throttle_ms);
+    let save = |checkpoint: &Checkpoint| {
+        if let Some(path) = path
+            && let Err(e) = checkpoint.save_to_file(path)
+        {
+            println!("Could not save the VDF checkpoint: {}", e);
+        }
+    };
+
+    let mut checkpoint = match path.map(Checkpoint::load_from_file) {
+        Some(Ok(checkpoint)) if checkpoint.fits(config, t, &disc, &from) => {
+            println!("Resuming the VDF search at {} squarings", checkpoint.done);
+            send(SearchEvent::Resumed(checkpoint.done));
+            checkpoint
+        }
+        Some(Err(e)) if path.is_some_and(Path::exists) => {
+            println!("VDF checkpoint rejected: {}", e);
+            fresh_checkpoint(config, t, &disc, from, 0)
+        }
+        // No checkpoint yet, or one for different settings or another round
+        _ => fresh_checkpoint(config, t, &disc, from, 0),
+    };
+
+    loop {
+        let round_start = checkpoint.done - checkpoint.iteration;
+        let mut latest = checkpoint.clone();
+        let result = vdf::eval_from(
+            &disc,
+            &checkpoint.x,
+            t,
+            checkpoint.iteration,
+            checkpoint.y.clone(),
+            stop,
+            |iteration, y| {
+                latest.iteration = iteration;
+                latest.done = round_start + iteration;
+                latest.y = y.clone();
+                if iteration % PROGRESS_INTERVAL == 0 {
+                    send(SearchEvent::Progress(latest.done));
+                }
+                if iteration % CHECKPOINT_INTERVAL == 0 {
+                    save(&latest);
+                }
+                if !throttle.is_zero() {
+                    thread::sleep(throttle);
+                }
+            },
+        );
+
+        let Some((y, pi)) = result else {
+            // Stopped, keep what was done for the next Dig
+            save(&latest);
+            println!("VDF search stopped");
+            return;
+        };
+        let done = round_start + t;
+        send(SearchEvent::Progress(done));
+
+        // The next round is saved before the find is sent, so it is never handed out twice
+        let proof = VdfProof {
+            x: checkpoint.x.clone(),
+            t,
+            y,
+            pi,
+        };
+        checkpoint = fresh_checkpoint(config, t, &disc, vdf::next_input(&proof.y), done);
+        save(&checkpoint);
+        if config.target.matches(&proof.y) {
+            send(SearchEvent::Found(proof));
+        } else {
+            send(SearchEvent::Missed(proof));
+        }
+    }
+}
+
+// The start of a round from input x
+fn fresh_checkpoint(config: &VdfConfig, t: u64, disc: &BigInt, x: BigInt, done: u64) -> Checkpoint {
+    Checkpoint {
+        config: config.clone(),
+        t,
+        y: vdf::first_form(disc, &x),
+        x,
+        iteration: 0,
+        done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf::Discriminant;
+    use std::time::Instant;
+
+    fn wait_for_found(miner: &Miner) -> VdfProof {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            for event in miner.events() {
+                if let SearchEvent::Found(proof) = event {
+                    return proof;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("nothing found in time");
+    }
+
+    #[test]
+    fn test_runs_stop_and_restart_on_one_worker() {
+        let mut miner = Miner::new(None, || {});
+        let config = VdfConfig {
+            input: "3".to_string(),
+            ..VdfConfig::default()
+        };
+        miner.start(config.clone(), 8, config.input());
+        assert!(miner.is_running());
+
+        // Rounds are chained, the second starts from the first one's output
+        let first = wait_for_found(&miner);
+        let second = wait_for_found(&miner);
+        assert!(vdf::verify_proof(&first) && vdf::verify_proof(&second));
+        assert_eq!(second.x, vdf::next_input(&first.y));
+
+        // Stopping does not wait, a new run starts over from its own input
+        let started = Instant::now();
+        miner.stop();
+        assert!(!miner.is_running());
+        assert!(started.elapsed() < Duration::from_millis(50));
+        miner.start(config.clone(), 8, config.input());
+        let restarted = loop {
+            let proof = wait_for_found(&miner);
+            if proof.x == config.input() {
+                break proof;
+            }
+        };
+        assert_eq!(restarted.y, first.y);
+
+        miner.shutdown();
+        assert!(miner.handle.is_none());
+    }
+
+    #[test]
+    fn test_shutdown_does_not_wait_for_a_new_discriminant() {
+        let mut miner = Miner::new(None, || {});
+        let config = VdfConfig {
+            input: format!("uncached {}", std::process::id()),
+            discriminant: Discriminant::Generated { bits: 2048 },
+            ..VdfConfig::default()
+        };
+        miner.start(config.clone(), 8, config.input());
+        thread::sleep(Duration::from_millis(20));
+
+        let started = Instant::now();
+        miner.shutdown();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(miner.events().is_empty());
+    }
+
+    fn temp_checkpoint(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("key"));
+        path
+    }
+
+    #[test]
+    fn test_tampered_checkpoint_is_rejected() {
+        let path = temp_checkpoint("tampered-checkpoint");
+        let config = VdfConfig::default();
+        let disc = config.discriminant();
+        let x = config.input();
+        let checkpoint = Checkpoint {
+            y: vdf::first_form(&disc, &x),
+            x,
+            config: config.clone(),
+            t: 64,
+            iteration: 10,
+            done: 10,
+        };
+        checkpoint.save_to_file(&path).unwrap();
+        assert_eq!(Checkpoint::load_from_file(&path).unwrap(), checkpoint);
+
+        // Moving the count on, or swapping in another form, breaks the MAC
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::write(
+            &path,
+            saved.replace("\"iteration\": 10", "\"iteration\": 60"),
+        )
+        .unwrap();
+        assert!(Checkpoint::load_from_file(&path).is_err());
+        let mut forged: serde_json::Value = serde_json::from_str(&saved).unwrap();
+        forged["checkpoint"]["y"][0] = serde_json::Value::from("1");
+        fs::write(&path, forged.to_string()).unwrap();
+        assert!(Checkpoint::load_from_file(&path).is_err());
+
+        // Without the key the checkpoint cannot be trusted either
+        fs::write(&path, &saved).unwrap();
+        fs::remove_file(path.with_extension("key")).unwrap();
+        assert!(Checkpoint::load_from_file(&path).is_err());
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("key"));
+    }
+
+    #[test]
+    fn test_stopped_run_resumes_from_checkpoint() {
+        let path = temp_checkpoint("resumed-checkpoint");
+        let config = VdfConfig {
+            input: "resume".to_string(),
+            ..VdfConfig::default()
+        };
+        let t = CHECKPOINT_INTERVAL * 2;
+        let mut miner = Miner::new(Some(path.clone()), || {});
+
+        // Stop part way through the first round
+        miner.start(config.clone(), t, config.input());
+        let deadline = Instant::now() + Duration::from_secs(30);
+        'waiting: while Instant::now() < deadline {
+            for event in miner.events() {
+                if matches!(event, SearchEvent::Progress(done) if done >= PROGRESS_INTERVAL * 2) {
+                    break 'waiting;
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        miner.stop();
+        miner.shutdown();
+        let saved = Checkpoint::load_from_file(&path).unwrap();
+        assert!(saved.iteration > 0 && saved.iteration < t);
+
+        // The next run picks up there and still finds the right output
+        let mut miner = Miner::new(Some(path.clone()), || {});
+        miner.start(config.clone(), t, config.input());
+        let mut resumed = None;
+        let mut found = None;
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while found.is_none() && Instant::now() < deadline {
+            for event in miner.events() {
+                match event {
+                    SearchEvent::Resumed(done) => resumed = Some(done),
+                    SearchEvent::Found(proof) if found.is_none() => found = Some(proof),
+                    _ => {}
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        let first = found.expect("nothing found in time");
+        assert_eq!(resumed, Some(saved.done));
+        assert_eq!(first.x, config.input());
+        assert!(vdf::verify_proof(&first));
+        miner.shutdown();
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("key"));
+    }
+}
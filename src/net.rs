@@ -0,0 +1,1164 @@
+// File: src/net.rs
+// Network play over TCP. The host runs the authoritative GameState, clients send turn
+// requests and receive the state back. Messages are serde_json, one message per line.
+// Dice are agreed with a commit-reveal round (see fair_dice) so the host cannot pick them.
+use crate::fair_dice::{self, Commitment, Nonce};
+use crate::game_state::GameState;
+use crate::objects::Controller;
+use crate::vdf::{DigLedger, VdfProof};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 7878;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+// Players that have not committed by then are left out of the roll, a reveal missing by
+// then aborts the roll, since dropping a reveal that was committed to would let the host
+// choose between seeds
+const ROUND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Join {
+        name: String,
+        seat: Option<usize>,
+    }, // seat is the one held before a reconnect
+    Roll,
+    ChooseDie(u32),
+    UseReroll,
+    Found(Box<VdfProof>), // A Dig round done by this player, checked by the host
+    Commit {
+        roll_id: u64,
+        commitment: Commitment,
+    },
+    Reveal {
+        roll_id: u64,
+        nonce: Nonce,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerMessage {
+    Welcome {
+        seat: usize,
+    },
+    Rejected(String),
+    Full(Box<GameState>), // Sent on join and whenever the board or players change
+    Diff(StateDiff),      // Sent when only the turn progressed
+    RollRequest {
+        roll_id: u64,
+    }, // Asks every player for a commitment
+    Commitments {
+        roll_id: u64,
+        commitments: Vec<Commitment>,
+    }, // Everyone's, in seed order
+    RollAborted {
+        roll_id: u64,
+    }, // Someone did not reveal in time, the turn has to be rolled again
+    TurnResult {
+        action: TurnAction,
+        diff: StateDiff,
+    }, // Lets clients replay and check the turn
+}
+
+// What was played, enough for a client to replay the turn on its own state
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TurnAction {
+    Roll {
+        roll_id: u64,
+        reveals: Vec<Nonce>, // Lined up with the commitments, every one has to be there
+    },
+    ChooseDie(u32),
+    UseReroll,
+}
+
+// The parts of a GameState that change while playing a board
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateDiff {
+    pub users: Vec<UserUpdate>, // Only the users that moved
+    pub current_player: usize,
+    pub winner: Option<usize>,
+    pub pending_dice: Vec<u32>,
+    pub new_game: bool,
+    pub extra_die: bool,
+    pub turn: u32,
+    pub dig_ledger: Option<DigLedger>, // Only when a Dig round was handed in
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserUpdate {
+    pub seat: usize,
+    pub position: u32,
+    pub skip_turns: u32,
+    pub rerolls: u32,
+    pub shields: u32,
+}
+
+impl StateDiff {
+    // None when more than the turn progress changed and the full state has to be sent
+    pub fn between(old: &GameState, new: &GameState) -> Option<StateDiff> {
+        if old.settings != new.settings
+            || old.grid_size != new.grid_size
+            || old.arrows != new.arrows
+            || old.special_squares != new.special_squares
+            || old.users.len() != new.users.len()
+        {
+            return None;
+        }
+
+        let users = old
+            .users
+            .iter()
+            .zip(&new.users)
+            .enumerate()
+            .filter(|(_, (old_user, new_user))| {
+                old_user.position != new_user.position
+                    || old_user.skip_turns != new_user.skip_turns
+                    || old_user.rerolls != new_user.rerolls
+                    || old_user.shields != new_user.shields
+            })
+            .map(|(seat, (_, new_user))| UserUpdate {
+                seat,
+                position: new_user.position,
+                skip_turns: new_user.skip_turns,
+                rerolls: new_user.rerolls,
+                shields: new_user.shields,
+            })
+            .collect();
+
+        Some(StateDiff {
+            users,
+            current_player: new.current_player,
+            winner: new.winner,
+            pending_dice: new.pending_dice.clone(),
+            new_game: new.new_game,
+            extra_die: new.extra_die,
+            turn: new.turn,
+            dig_ledger: (old.dig_ledger != new.dig_ledger).then(|| new.dig_ledger.clone()),
+        })
+    }
+
+    // True when applying the diff to the old state would not change it
+    pub fn is_empty_for(&self, old: &GameState) -> bool {
+        self.users.is_empty()
+            && self.current_player == old.current_player
+            && self.winner == old.winner
+            && self.pending_dice == old.pending_dice
+            && self.new_game == old.new_game
+            && self.extra_die == old.extra_die
+            && self.turn == old.turn
+            && self
+                .dig_ledger
+                .as_ref()
+                .is_none_or(|ledger| *ledger == old.dig_ledger)
+    }
+
+    pub fn apply(&self, state: &mut GameState) {
+        for update in &self.users {
+            if let Some(user) = state.users.get_mut(update.seat) {
+                user.position = update.position;
+                user.skip_turns = update.skip_turns;
+                user.rerolls = update.rerolls;
+                user.shields = update.shields;
+            }
+        }
+        state.current_player = self.current_player;
+        state.winner = self.winner;
+        state.pending_dice = self.pending_dice.clone();
+        state.new_game = self.new_game;
+        state.extra_die = self.extra_die;
+        state.turn = self.turn;
+        if let Some(ledger) = &self.dig_ledger {
+            state.dig_ledger = ledger.clone();
+        }
+    }
+}
+
+fn send_line<T: Serialize>(stream: &mut TcpStream, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+// Either end of a network game
+pub enum Session {
+    Host(Box<Host>),
+    Client(Client),
+}
+
+impl Session {
+    // Short description for the lobby screen
+    pub fn status(&self) -> String {
+        match self {
+            Session::Host(host) => format!(
+                "Hosting on port {}, {} connected",
+                host.port(),
+                host.remote_seats().len()
+            ),
+            Session::Client(client) if client.mismatches > 0 => format!(
+                "Warning: {} host turns did not match the dice",
+                client.mismatches
+            ),
+            Session::Client(client) if client.aborted > 0 => format!(
+                "Warning: the host called off {} dice rounds after seeing them",
+                client.aborted
+            ),
+            Session::Client(client) => match (client.is_connected(), client.seat) {
+                (true, Some(seat)) if client.left_out > 0 => format!(
+                    "Connected as player {}, left out of {} dice rounds",
+                    seat + 1,
+                    client.left_out
+                ),
+                (true, Some(seat)) => format!("Connected as player {}", seat + 1),
+                (true, None) => "Connected, waiting for a seat".to_string(),
+                (false, _) => "Connection lost, reconnecting...".to_string(),
+            },
+        }
+    }
+}
+
+enum HostEvent {
+    Message(usize, ClientMessage),
+    Left(usize),
+}
+
+struct Connection {
+    id: usize,
+    stream: TcpStream,
+    seat: Option<usize>,
+}
+
+// A commit-reveal round in progress on the host
+struct RollRound {
+    roll_id: u64,
+    revealing: bool, // False while commitments are being collected
+    deadline: Instant,
+    participants: Vec<Participant>,
+}
+
+struct Participant {
+    id: Option<usize>, // Connection id, None for the host
+    commitment: Option<Commitment>,
+    nonce: Option<Nonce>,
+}
+
+impl RollRound {
+    fn participant(&mut self, id: usize) -> Option<&mut Participant> {
+        self.participants
+            .iter_mut()
+            .find(|participant| participant.id == Some(id))
+    }
+
+    fn commitments(&self) -> Vec<Commitment> {
+        self.participants
+            .iter()
+            .filter_map(|participant| participant.commitment)
+            .collect()
+    }
+}
+
+pub struct Host {
+    port: u16,
+    events: Receiver<HostEvent>,
+    connections: Arc<Mutex<Vec<Connection>>>,
+    stop: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+    last_sent: Option<GameState>,
+    round: Option<RollRound>,
+    next_roll_id: u64,
+}
+
+impl Host {
+    // Port 0 picks any free port, see port()
+    pub fn start(port: u16) -> io::Result<Host> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let port = listener.local_addr()?.port();
+        // Non blocking so the accept loop can notice the stop flag
+        listener.set_nonblocking(true)?;
+
+        let (tx, rx) = mpsc::channel();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let accept_connections = connections.clone();
+        let accept_stop = stop.clone();
+        let accept_thread = thread::spawn(move || {
+            let mut next_id = 0;
+            while !accept_stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, address)) => {
+                        println!("Player connected from {}", address);
+                        let _ = stream.set_nonblocking(false);
+                        let Ok(writer) = stream.try_clone() else {
+                            continue;
+                        };
+                        accept_connections.lock().unwrap().push(Connection {
+                            id: next_id,
+                            stream: writer,
+                            seat: None,
+                        });
+                        let tx = tx.clone();
+                        let id = next_id;
+                        thread::spawn(move || read_client(id, stream, tx));
+                        next_id += 1;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL);
+                    }
+                    Err(e) => println!("Accept failed: {}", e),
+                }
+            }
+        });
+
+        println!("Hosting on port {}", port);
+        Ok(Host {
+            port,
+            events: rx,
+            connections,
+            stop,
+            accept_thread: Some(accept_thread),
+            last_sent: None,
+            round: None,
+            next_roll_id: 0,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Seats played from another window, these cannot be played locally
+    pub fn remote_seats(&self) -> Vec<usize> {
+        self.connections
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|connection| connection.seat)
+            .collect()
+    }
+
+    // Handles joins, leaves, turn requests and dice rounds, returns true if anything happened
+    pub fn poll(&mut self, state: &mut GameState) -> bool {
+        let mut changed = false;
+        while let Ok(event) = self.events.try_recv() {
+            changed = true;
+            match event {
+                HostEvent::Message(id, ClientMessage::Join { name, seat }) => {
+                    self.join(id, name, seat, state);
+                }
+                HostEvent::Message(
+                    id,
+                    ClientMessage::Commit {
+                        roll_id,
+                        commitment,
+                    },
+                ) => {
+                    if let Some(round) = &mut self.round
+                        && round.roll_id == roll_id
+                        && !round.revealing
+                        && let Some(participant) = round.participant(id)
+                    {
+                        participant.commitment = Some(commitment);
+                    }
+                }
+                HostEvent::Message(id, ClientMessage::Reveal { roll_id, nonce }) => {
+                    if let Some(round) = &mut self.round
+                        && round.roll_id == roll_id
+                        && round.revealing
+                        && let Some(participant) = round.participant(id)
+                        && let Some(commitment) = participant.commitment
+                    {
+                        if fair_dice::verify_reveal(roll_id, &nonce, &commitment) {
+                            participant.nonce = Some(nonce);
+                        } else {
+                            println!("Player {} revealed a nonce that does not match", id);
+                        }
+                    }
+                }
+                HostEvent::Message(id, ClientMessage::Found(proof)) => {
+                    if let Some(seat) = self.seat_of(id)
+                        && let Err(e) = state.award_find(seat, &proof)
+                    {
+                        println!("Player {} sent a find that was refused: {}", id, e);
+                    }
+                }
+                HostEvent::Message(id, message) => {
+                    // Only the player whose turn it is may act
+                    if self.seat_of(id) != Some(state.current_player) {
+                        continue;
+                    }
+                    match message {
+                        ClientMessage::Roll => self.request_roll(state),
+                        ClientMessage::ChooseDie(value) => self.choose_die(state, value),
+                        ClientMessage::UseReroll => self.use_reroll(state),
+                        _ => {}
+                    }
+                }
+                HostEvent::Left(id) => {
+                    println!("Player {} disconnected", id);
+                    self.connections
+                        .lock()
+                        .unwrap()
+                        .retain(|connection| connection.id != id);
+                }
+            }
+        }
+        self.advance_round(state) || changed
+    }
+
+    // Starts a dice round for the current player's turn, the turn is played once it completes
+    pub fn request_roll(&mut self, state: &GameState) {
+        if self.round.is_some() || state.winner.is_some() || !state.pending_dice.is_empty() {
+            return;
+        }
+
+        let roll_id = self.next_roll_id;
+        self.next_roll_id += 1;
+
+        // The host takes part like everyone else, its own nonce is known straight away
+        let nonce = fair_dice::new_nonce();
+        let mut participants = vec![Participant {
+            id: None,
+            commitment: Some(fair_dice::commit(roll_id, &nonce)),
+            nonce: Some(nonce),
+        }];
+        for connection in self.connections.lock().unwrap().iter() {
+            if connection.seat.is_some() {
+                participants.push(Participant {
+                    id: Some(connection.id),
+                    commitment: None,
+                    nonce: None,
+                });
+            }
+        }
+
+        self.round = Some(RollRound {
+            roll_id,
+            revealing: false,
+            deadline: Instant::now() + ROUND_TIMEOUT,
+            participants,
+        });
+        self.broadcast(&ServerMessage::RollRequest { roll_id });
+    }
+
+    // Moves the round on once everyone has answered or the time is up, true if a turn was played
+    fn advance_round(&mut self, state: &mut GameState) -> bool {
+        let Some(mut round) = self.round.take() else {
+            return false;
+        };
+        let timed_out = Instant::now() >= round.deadline;
+
+        if !round.revealing {
+            let waiting = round.participants.iter().any(|p| p.commitment.is_none());
+            if waiting && !timed_out {
+                self.round = Some(round);
+                return false;
+            }
+            round.participants.retain(|p| p.commitment.is_some());
+            round.revealing = true;
+            round.deadline = Instant::now() + ROUND_TIMEOUT;
+            self.broadcast(&ServerMessage::Commitments {
+                roll_id: round.roll_id,
+                commitments: round.commitments(),
+            });
+            // With no one else taking part the host's reveal is all there is
+            if round.participants.len() > 1 {
+                self.round = Some(round);
+                return false;
+            }
+        }
+
+        let waiting = round.participants.iter().any(|p| p.nonce.is_none());
+        if waiting {
+            if timed_out {
+                println!(
+                    "Dice round {} aborted, not everyone revealed",
+                    round.roll_id
+                );
+                self.broadcast(&ServerMessage::RollAborted {
+                    roll_id: round.roll_id,
+                });
+            } else {
+                self.round = Some(round);
+            }
+            return false;
+        }
+
+        let reveals: Vec<Nonce> = round.participants.iter().filter_map(|p| p.nonce).collect();
+        let seed = match fair_dice::verify_round(round.roll_id, &round.commitments(), &reveals) {
+            Ok(seed) => seed,
+            Err(e) => {
+                println!("Dice round {} failed: {}", round.roll_id, e);
+                return false;
+            }
+        };
+
+        // Clients replay from the last state they were sent
+        self.sync(state);
+        state.take_turn_with_seed(seed);
+        self.send_turn_result(
+            state,
+            TurnAction::Roll {
+                roll_id: round.roll_id,
+                reveals,
+            },
+        );
+        true
+    }
+
+    pub fn choose_die(&mut self, state: &mut GameState, value: u32) {
+        self.sync(state);
+        state.choose_die(value);
+        self.send_turn_result(state, TurnAction::ChooseDie(value));
+    }
+
+    pub fn use_reroll(&mut self, state: &mut GameState) {
+        if self.round.is_some() {
+            return; // Too late, the dice for this turn are already being drawn
+        }
+        self.sync(state);
+        if state.use_reroll() {
+            self.send_turn_result(state, TurnAction::UseReroll);
+        }
+    }
+
+    fn send_turn_result(&mut self, state: &GameState, action: TurnAction) {
+        let diff = self
+            .last_sent
+            .as_ref()
+            .and_then(|last| StateDiff::between(last, state));
+        let message = match diff {
+            Some(diff) => ServerMessage::TurnResult { action, diff },
+            None => ServerMessage::Full(Box::new(state.clone())),
+        };
+        self.broadcast(&message);
+        self.last_sent = Some(state.clone());
+    }
+
+    fn seat_of(&self, id: usize) -> Option<usize> {
+        self.connections
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|connection| connection.id == id)
+            .and_then(|connection| connection.seat)
+    }
+
+    // The first seat is the host's, the others go to clients in order
+    fn join(&mut self, id: usize, name: String, requested: Option<usize>, state: &mut GameState) {
+        let taken = self.remote_seats();
+        let free = |seat: &usize| *seat > 0 && *seat < state.users.len() && !taken.contains(seat);
+        let seat = requested
+            .filter(free)
+            .or_else(|| (1..state.users.len()).find(free));
+
+        let Some(seat) = seat else {
+            self.send_to(id, &ServerMessage::Rejected("The game is full".to_string()));
+            return;
+        };
+
+        let mut players = state.settings.players.clone();
+        if let Some(profile) = players.get_mut(seat) {
+            profile.name = name;
+            profile.controller = Controller::Human;
+        }
+        state.update_players(players);
+
+        if let Some(connection) = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            connection.seat = Some(seat);
+        }
+        self.send_to(id, &ServerMessage::Welcome { seat });
+        // Everyone gets the new name, the new player gets the whole game
+        self.last_sent = None;
+        self.sync(state);
+    }
+
+    // Sends the state to every client if it changed since the last call
+    pub fn sync(&mut self, state: &GameState) {
+        let message = match &self.last_sent {
+            Some(last) => match StateDiff::between(last, state) {
+                Some(diff) if diff.is_empty_for(last) => return,
+                Some(diff) => ServerMessage::Diff(diff),
+                None => ServerMessage::Full(Box::new(state.clone())),
+            },
+            None => ServerMessage::Full(Box::new(state.clone())),
+        };
+        self.broadcast(&message);
+        self.last_sent = Some(state.clone());
+    }
+
+    fn broadcast(&self, message: &ServerMessage) {
+        for connection in self.connections.lock().unwrap().iter_mut() {
+            if connection.seat.is_some() {
+                // A failed write shows up as a leave from the reader thread
+                let _ = send_line(&mut connection.stream, message);
+            }
+        }
+    }
+
+    fn send_to(&self, id: usize, message: &ServerMessage) {
+        if let Some(connection) = self
+            .connections
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|connection| connection.id == id)
+        {
+            let _ = send_line(&mut connection.stream, message);
+        }
+    }
+}
+
+impl Drop for Host {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for connection in self.connections.lock().unwrap().iter() {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+        // The listener closes with the thread, freeing the port
+        if let Some(handle) = self.accept_thread.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn read_client(id: usize, stream: TcpStream, tx: Sender<HostEvent>) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        match serde_json::from_str::<ClientMessage>(&line) {
+            Ok(message) => {
+                if tx.send(HostEvent::Message(id, message)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => println!("Bad message from player {}: {}", id, e),
+        }
+    }
+    let _ = tx.send(HostEvent::Left(id));
+}
+
+// The client's side of the dice round in progress
+struct ClientRound {
+    roll_id: u64,
+    nonce: Nonce,
+    commitments: Vec<Commitment>, // Empty until the host sends everyone's
+    revealed: bool,               // Our commitment made the list and our nonce went out
+}
+
+pub struct Client {
+    pub seat: Option<usize>,
+    pub mismatches: u32, // Host turns that did not replay to the same result
+    pub left_out: u32,   // Dice rounds played without us, their rolls are taken on trust
+    pub aborted: u32,    // Dice rounds called off after we revealed, so the host saw the roll
+    round: Option<ClientRound>,
+    events: Receiver<ServerMessage>,
+    stream: Arc<Mutex<Option<TcpStream>>>, // None while reconnecting
+    stop: Arc<AtomicBool>,
+}
+
+impl Client {
+    // The first connection is made here so a wrong address is reported straight away,
+    // after that the connection is kept up in the background
+    pub fn connect(address: &str, name: &str) -> io::Result<Client> {
+        let stream = TcpStream::connect(address)?;
+        let (tx, rx) = mpsc::channel();
+        let shared = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let address = address.to_string();
+        let name = name.to_string();
+        let thread_shared = shared.clone();
+        let thread_stop = stop.clone();
+        thread::spawn(move || run_client(address, name, stream, thread_shared, tx, thread_stop));
+
+        Ok(Client {
+            seat: None,
+            mismatches: 0,
+            left_out: 0,
+            aborted: 0,
+            round: None,
+            events: rx,
+            stream: shared,
+            stop,
+        })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.lock().unwrap().is_some()
+    }
+
+    pub fn send(&self, message: &ClientMessage) {
+        if let Some(stream) = self.stream.lock().unwrap().as_mut()
+            && let Err(e) = send_line(stream, message)
+        {
+            println!("Could not send to host: {}", e);
+        }
+    }
+
+    // Messages received since the last call, dice rounds are answered here
+    pub fn poll(&mut self) -> Vec<ServerMessage> {
+        let messages: Vec<ServerMessage> = self.events.try_iter().collect();
+        for message in &messages {
+            match message {
+                ServerMessage::Welcome { seat } => self.seat = Some(*seat),
+                ServerMessage::RollRequest { roll_id } => {
+                    let nonce = fair_dice::new_nonce();
+                    self.send(&ClientMessage::Commit {
+                        roll_id: *roll_id,
+                        commitment: fair_dice::commit(*roll_id, &nonce),
+                    });
+                    self.round = Some(ClientRound {
+                        roll_id: *roll_id,
+                        nonce,
+                        commitments: Vec::new(),
+                        revealed: false,
+                    });
+                }
+                ServerMessage::Commitments {
+                    roll_id,
+                    commitments,
+                } => {
+                    let Some(round) = &mut self.round else {
+                        continue;
+                    };
+                    if round.roll_id != *roll_id {
+                        continue;
+                    }
+                    round.commitments = commitments.clone();
+                    // Only reveal once our commitment is fixed in the list
+                    let own = fair_dice::commit(round.roll_id, &round.nonce);
+                    if commitments.contains(&own) {
+                        round.revealed = true;
+                        let reveal = ClientMessage::Reveal {
+                            roll_id: round.roll_id,
+                            nonce: round.nonce,
+                        };
+                        self.send(&reveal);
+                    } else {
+                        println!("Left out of dice round {}", roll_id);
+                    }
+                }
+                ServerMessage::RollAborted { roll_id }
+                    if self.round.as_ref().is_some_and(|r| r.roll_id == *roll_id) =>
+                {
+                    // A host that re-rolls once it has seen the reveals can pick its roll
+                    if self.round.take().is_some_and(|round| round.revealed) {
+                        println!("Dice round {} was aborted after our reveal", roll_id);
+                        self.aborted += 1;
+                    } else {
+                        println!("Dice round {} was aborted by the host", roll_id);
+                    }
+                }
+                _ => {}
+            }
+        }
+        messages
+    }
+
+    // Replays the host's turn on our copy of the state and checks it against the host's
+    // result. The host's result is kept either way, returns false if they differ.
+    pub fn apply_turn(
+        &mut self,
+        state: &mut GameState,
+        action: &TurnAction,
+        diff: &StateDiff,
+    ) -> bool {
+        let mut expected = state.clone();
+        let replayed = match action {
+            TurnAction::Roll { roll_id, reveals } => match self.round.take() {
+                // Our commitment came too late for the round, which is not the host cheating
+                Some(round) if round.roll_id == *roll_id && !round.revealed => {
+                    println!("Dice round {} went ahead without us", roll_id);
+                    self.left_out += 1;
+                    false
+                }
+                // Our nonce is what makes the roll fair for us, a round without it is refused
+                Some(round) if round.roll_id == *roll_id && !reveals.contains(&round.nonce) => {
+                    println!("Dice round {} left out our reveal", roll_id);
+                    self.mismatches += 1;
+                    false
+                }
+                Some(round) if round.roll_id == *roll_id => {
+                    match fair_dice::verify_round(*roll_id, &round.commitments, reveals) {
+                        Ok(seed) => {
+                            expected.take_turn_with_seed(seed);
+                            true
+                        }
+                        Err(e) => {
+                            println!("Dice round {} does not verify: {}", roll_id, e);
+                            self.mismatches += 1;
+                            false
+                        }
+                    }
+                }
+                // Joined part way through the round, nothing to check against
+                _ => false,
+            },
+            TurnAction::ChooseDie(value) => {
+                expected.choose_die(*value);
+                true
+            }
+            TurnAction::UseReroll => expected.use_reroll(),
+        };
+
+        let mut actual = state.clone();
+        diff.apply(&mut actual);
+        // Dig rounds handed in meanwhile are not part of the turn
+        expected.dig_ledger = actual.dig_ledger.clone();
+        if replayed {
+            let matches = StateDiff::between(&expected, &actual)
+                .is_some_and(|difference| difference.is_empty_for(&expected));
+            if matches {
+                // The replay also carries the dice for the rest of the turn
+                *state = expected;
+                return true;
+            }
+            println!("The host's turn does not match the agreed dice");
+            self.mismatches += 1;
+        }
+        *state = actual;
+        false
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// Reads from the host, reconnecting and asking for the same seat whenever the connection drops
+fn run_client(
+    address: String,
+    name: String,
+    mut stream: TcpStream,
+    shared: Arc<Mutex<Option<TcpStream>>>,
+    tx: Sender<ServerMessage>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut seat = None;
+    loop {
+        if let Ok(mut writer) = stream.try_clone() {
+            let join = ClientMessage::Join {
+                name: name.clone(),
+                seat,
+            };
+            if send_line(&mut writer, &join).is_ok() {
+                *shared.lock().unwrap() = Some(writer);
+            }
+        }
+
+        for line in BufReader::new(&stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match serde_json::from_str::<ServerMessage>(&line) {
+                Ok(message) => {
+                    if let ServerMessage::Welcome { seat: welcome_seat } = message {
+                        seat = Some(welcome_seat);
+                    }
+                    if tx.send(message).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => println!("Bad message from host: {}", e),
+            }
+        }
+        *shared.lock().unwrap() = None;
+
+        stream = loop {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(RECONNECT_DELAY);
+            if let Ok(stream) = TcpStream::connect(&address) {
+                println!("Reconnected to {}", address);
+                break stream;
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vdf;
+    use std::time::Instant;
+
+    // Polls both ends until the client has received a message matching the check
+    fn wait_for(
+        host: &mut Host,
+        host_state: &mut GameState,
+        client: &mut Client,
+        mut check: impl FnMut(&ServerMessage) -> bool,
+    ) -> Vec<ServerMessage> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut received = Vec::new();
+        while Instant::now() < deadline {
+            host.poll(host_state);
+            host.sync(host_state);
+            for message in client.poll() {
+                let done = check(&message);
+                received.push(message);
+                if done {
+                    return received;
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out, received {:?}", received);
+    }
+
+    #[test]
+    fn test_join_roll_and_reconnect() {
+        let mut host = Host::start(0).unwrap();
+        let port = host.port();
+        let mut host_state = GameState::new();
+        let address = format!("127.0.0.1:{}", port);
+
+        let mut client = Client::connect(&address, "Guest").unwrap();
+        let received = wait_for(&mut host, &mut host_state, &mut client, |message| {
+            matches!(message, ServerMessage::Full(_))
+        });
+        assert_eq!(client.seat, Some(1));
+        assert_eq!(host_state.users[1].name, "Guest");
+        assert_eq!(host.remote_seats(), vec![1]);
+
+        let mut client_state = match received.last() {
+            Some(ServerMessage::Full(state)) => *state.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(client_state.users[1].name, "Guest");
+
+        // Out of turn requests are ignored
+        client.send(&ClientMessage::Roll);
+        thread::sleep(Duration::from_millis(100));
+        host.poll(&mut host_state);
+        assert_eq!(host_state.current_player, 0);
+
+        // The host's turn and then the client's go through a dice round each,
+        // and the client's replay of both agrees with the host
+        for _ in 0..2 {
+            if host_state.current_player == 0 {
+                host.request_roll(&host_state);
+            } else {
+                client.send(&ClientMessage::Roll);
+            }
+            let received = wait_for(&mut host, &mut host_state, &mut client, |message| {
+                matches!(message, ServerMessage::TurnResult { .. })
+            });
+            assert!(
+                received
+                    .iter()
+                    .any(|message| matches!(message, ServerMessage::Commitments { .. }))
+            );
+            let Some(ServerMessage::TurnResult { action, diff }) = received.last() else {
+                unreachable!();
+            };
+            assert!(client.apply_turn(&mut client_state, action, diff));
+            assert_eq!(client_state.users[0].position, host_state.users[0].position);
+            assert_eq!(client_state.users[1].position, host_state.users[1].position);
+            assert_eq!(client_state.current_player, host_state.current_player);
+        }
+        assert_eq!(client.mismatches, 0);
+
+        // A result that does not follow from the action is flagged
+        let mut forged = StateDiff::between(&client_state, &client_state).unwrap();
+        forged.users.push(UserUpdate {
+            seat: 1,
+            position: host_state.last_square(),
+            skip_turns: 0,
+            rerolls: 0,
+            shields: 0,
+        });
+        assert!(!client.apply_turn(&mut client_state, &TurnAction::ChooseDie(1), &forged));
+        assert_eq!(client.mismatches, 1);
+
+        // A restarted host gets the client back in the same seat
+        drop(host);
+        let mut host = Host::start(port).unwrap();
+        let mut host_state = GameState::new();
+        wait_for(&mut host, &mut host_state, &mut client, |message| {
+            matches!(message, ServerMessage::Welcome { seat: 1 })
+        });
+        assert_eq!(host.remote_seats(), vec![1]);
+    }
+
+    // A client in the middle of a dice round, without a connection
+    fn client_in_round(roll_id: u64, nonce: Nonce, commitments: Vec<Commitment>) -> Client {
+        let revealed = commitments.contains(&fair_dice::commit(roll_id, &nonce));
+        Client {
+            seat: Some(1),
+            mismatches: 0,
+            left_out: 0,
+            aborted: 0,
+            round: Some(ClientRound {
+                roll_id,
+                nonce,
+                commitments,
+                revealed,
+            }),
+            events: mpsc::channel().1,
+            stream: Arc::new(Mutex::new(None)),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn test_client_rejects_a_round_without_its_reveal() {
+        let state = GameState::new();
+        let host_nonce = fair_dice::new_nonce();
+        let client_nonce = fair_dice::new_nonce();
+        let commitments = vec![
+            fair_dice::commit(4, &host_nonce),
+            fair_dice::commit(4, &client_nonce),
+        ];
+
+        // The host saw our reveal and played the turn with its own nonce only
+        let mut forged_state = state.clone();
+        forged_state.take_turn_with_seed(fair_dice::combine(4, &[host_nonce]));
+        let diff = StateDiff::between(&state, &forged_state).unwrap();
+        let action = TurnAction::Roll {
+            roll_id: 4,
+            reveals: vec![host_nonce],
+        };
+        let mut client = client_in_round(4, client_nonce, commitments.clone());
+        let mut client_state = state.clone();
+        assert!(!client.apply_turn(&mut client_state, &action, &diff));
+        assert_eq!(client.mismatches, 1);
+
+        // The same round with every reveal is accepted
+        let reveals = vec![host_nonce, client_nonce];
+        let mut fair_state = state.clone();
+        fair_state.take_turn_with_seed(fair_dice::combine(4, &reveals));
+        let diff = StateDiff::between(&state, &fair_state).unwrap();
+        let action = TurnAction::Roll {
+            roll_id: 4,
+            reveals,
+        };
+        let mut client = client_in_round(4, client_nonce, commitments);
+        let mut client_state = state.clone();
+        assert!(client.apply_turn(&mut client_state, &action, &diff));
+        assert_eq!(client.mismatches, 0);
+    }
+
+    #[test]
+    fn test_client_tells_rounds_left_out_from_aborted() {
+        let state = GameState::new();
+        let host_nonce = fair_dice::new_nonce();
+        let client_nonce = fair_dice::new_nonce();
+        let reveals = vec![host_nonce];
+        let mut host_state = state.clone();
+        host_state.take_turn_with_seed(fair_dice::combine(5, &reveals));
+        let diff = StateDiff::between(&state, &host_state).unwrap();
+        let action = TurnAction::Roll {
+            roll_id: 5,
+            reveals,
+        };
+
+        // Our commitment missed the list, the host's roll is kept without blame
+        let mut client = client_in_round(5, client_nonce, vec![fair_dice::commit(5, &host_nonce)]);
+        let mut client_state = state.clone();
+        assert!(!client.apply_turn(&mut client_state, &action, &diff));
+        assert_eq!((client.left_out, client.mismatches), (1, 0));
+        assert_eq!(client_state.users[0].position, host_state.users[0].position);
+
+        // A round called off after we revealed is counted, one called off before is not
+        let (sender, events) = mpsc::channel();
+        let commitments = vec![
+            fair_dice::commit(6, &host_nonce),
+            fair_dice::commit(6, &client_nonce),
+        ];
+        client = client_in_round(6, client_nonce, commitments);
+        client.events = events;
+        sender
+            .send(ServerMessage::RollAborted { roll_id: 6 })
+            .unwrap();
+        client.poll();
+        assert_eq!(client.aborted, 1);
+        client.round = Some(ClientRound {
+            roll_id: 7,
+            nonce: client_nonce,
+            commitments: Vec::new(),
+            revealed: false,
+        });
+        sender
+            .send(ServerMessage::RollAborted { roll_id: 7 })
+            .unwrap();
+        client.poll();
+        assert_eq!(client.aborted, 1);
+        assert!(client.round.is_none());
+    }
+
+    #[test]
+    fn test_host_aborts_a_round_with_a_missing_reveal() {
+        let mut host = Host::start(0).unwrap();
+        let mut state = GameState::new();
+        let before = state.clone();
+        let host_nonce = fair_dice::new_nonce();
+        host.round = Some(RollRound {
+            roll_id: 0,
+            revealing: true,
+            deadline: Instant::now(),
+            participants: vec![
+                Participant {
+                    id: None,
+                    commitment: Some(fair_dice::commit(0, &host_nonce)),
+                    nonce: Some(host_nonce),
+                },
+                Participant {
+                    id: Some(0),
+                    commitment: Some(fair_dice::commit(0, &fair_dice::new_nonce())),
+                    nonce: None,
+                },
+            ],
+        });
+
+        assert!(!host.advance_round(&mut state));
+        assert!(host.round.is_none());
+        assert_eq!(state.users[0].position, before.users[0].position);
+        assert_eq!(state.current_player, before.current_player);
+    }
+
+    #[test]
+    fn test_diff_only_for_turn_changes() {
+        let old = GameState::new();
+        let mut new = old.clone();
+        new.users[0].position = 7;
+        new.current_player = 1;
+
+        let diff = StateDiff::between(&old, &new).unwrap();
+        assert_eq!(diff.users.len(), 1);
+        let mut applied = old.clone();
+        diff.apply(&mut applied);
+        assert_eq!(applied.users[0].position, 7);
+        assert_eq!(applied.current_player, 1);
+        assert!(diff.dig_ledger.is_none());
+
+        // A Dig round handed in moves the chain on for the clients too
+        let round = vdf::prove(&new.dig_ledger.head(&new.settings.vdf), 1);
+        new.dig_ledger.record(&round);
+        StateDiff::between(&old, &new).unwrap().apply(&mut applied);
+        assert_eq!(applied.dig_ledger, new.dig_ledger);
+
+        new.reset();
+        assert!(StateDiff::between(&old, &new).is_none());
+    }
+}
@@ -1,5 +1,7 @@
+use crate::vdf::{VdfConfig, VdfProof};
 use curv::BigInt;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 //use std::collections::HashMap;
 
 // Non-drawable objects
@@ -8,14 +10,289 @@ pub struct User {
     pub user_id: u32,
     pub name: String,
     pub position: u32,
+    #[serde(default)]
+    pub skip_turns: u32,
+    #[serde(default = "default_user_color")]
+    pub color: u32, // Token colour (format: 0xRRGGBBAA)
+    #[serde(default)]
+    pub controller: Controller,
+    #[serde(default)]
+    pub rerolls: u32, // Re-roll tokens earned by digging
+    #[serde(default)]
+    pub shields: u32, // Snake shields earned by digging
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct GameSettings {
     pub game_id: BigInt,
-    pub difficulty: String,
+    pub difficulty: Difficulty,
     pub sound_enabled: bool,
     pub max_players: u32,
+    #[serde(default = "default_players")]
+    pub players: Vec<PlayerProfile>, // One entry per seat, its length is the player count
+    #[serde(default = "default_grid_size")]
+    pub grid_size: u32,
+    #[serde(default)]
+    pub rules: RuleSet,
+    #[serde(default)]
+    pub board_seed: BoardSeed,
+    #[serde(default)]
+    pub seed_proof: Option<VdfProof>, // Proof for game_id when it came from a VDF
+    #[serde(default)]
+    pub vdf: VdfConfig, // How Dig searches
+}
+
+// Where the seed for a new board comes from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum BoardSeed {
+    #[default]
+    Random,
+    // The seed is a VDF output over public text, so it cannot be picked by trying many seeds
+    Vdf {
+        input: String,
+        log_iterations: u32,
+    },
+    // Random seeds are tried until a board's games take min_turns to max_turns on average
+    // and the first player is at most max_advantage percent more likely to win
+    Fair {
+        min_turns: u32,
+        max_turns: u32,
+        max_advantage: u32,
+    },
+}
+
+impl BoardSeed {
+    pub fn iterations(log_iterations: u32) -> u64 {
+        1u64 << log_iterations.min(40)
+    }
+
+    // Seeds that take too long to work out while the window waits, a VDF seed runs
+    // the squarings and a fair one analyses up to FAIR_SEED_TRIES boards
+    pub fn is_slow(&self) -> bool {
+        matches!(self, BoardSeed::Vdf { .. } | BoardSeed::Fair { .. })
+    }
+}
+
+// Per-seat preferences used to create the users when a game starts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerProfile {
+    pub name: String,
+    #[serde(default = "default_user_color")]
+    pub color: u32,
+    #[serde(default)]
+    pub controller: Controller,
+}
+
+// Who makes the decisions for a player
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Controller {
+    #[default]
+    Human,
+    Ai(Bot), // Turns are played automatically using the bot's strategy
+}
+
+// Built in computer opponents, see ai::Strategy for what they decide
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bot {
+    Greedy, // Takes whichever choice ends furthest along the board
+    Random, // Picks any choice
+}
+
+impl Controller {
+    pub const ALL: [Controller; 3] = [
+        Controller::Human,
+        Controller::Ai(Bot::Greedy),
+        Controller::Ai(Bot::Random),
+    ];
+
+    pub fn is_ai(&self) -> bool {
+        matches!(self, Controller::Ai(_))
+    }
+}
+
+impl fmt::Display for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Controller::Human => "Human",
+            Controller::Ai(Bot::Greedy) => "Computer (greedy)",
+            Controller::Ai(Bot::Random) => "Computer (random)",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Colours offered for player tokens (format: 0xRRGGBBAA)
+pub const PLAYER_COLORS: [u32; 6] = [
+    0xE02020FF, // Red
+    0x2060E0FF, // Blue
+    0x20A040FF, // Green
+    0xF09010FF, // Orange
+    0x9030C0FF, // Purple
+    0x10A0A0FF, // Teal
+];
+
+impl PlayerProfile {
+    pub fn new(name: String, color: u32, controller: Controller) -> Self {
+        Self {
+            name,
+            color,
+            controller,
+        }
+    }
+
+    // Default profile for a seat, numbered from zero
+    pub fn for_seat(seat: usize) -> Self {
+        Self::new(
+            format!("Player {}", seat + 1),
+            PLAYER_COLORS[seat % PLAYER_COLORS.len()],
+            Controller::Human,
+        )
+    }
+}
+
+// House rules applied by the rules engine in GameState
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RuleSet {
+    pub overshoot: Overshoot,
+    pub special_squares: bool,
+    pub die_sides: u32,
+    pub choose_die: bool, // Roll two dice and move by the one the player picks
+}
+
+// What happens when a roll would take a player past the last square
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overshoot {
+    Stay,   // The move is forfeited
+    Bounce, // The player bounces back off the last square
+    Finish, // The player stops on the last square
+}
+
+impl Overshoot {
+    pub const ALL: [Overshoot; 3] = [Overshoot::Stay, Overshoot::Bounce, Overshoot::Finish];
+
+    // Where a move of count from square ends on a board of last_square squares. A bounce
+    // goes back and forth between the ends, so no roll can take it below square 0.
+    pub fn land(self, square: u32, count: u32, last_square: u32) -> u32 {
+        let reach = square.saturating_add(count);
+        if reach <= last_square {
+            return reach;
+        }
+        match self {
+            Overshoot::Stay => square,
+            Overshoot::Bounce => {
+                let reach = reach % (last_square * 2).max(1);
+                if reach <= last_square {
+                    reach
+                } else {
+                    last_square * 2 - reach
+                }
+            }
+            Overshoot::Finish => last_square,
+        }
+    }
+}
+
+impl fmt::Display for Overshoot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Overshoot::Stay => "Stay",
+            Overshoot::Bounce => "Bounce",
+            Overshoot::Finish => "Finish",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            overshoot: Overshoot::Stay,
+            special_squares: true,
+            die_sides: 5,
+            choose_die: false,
+        }
+    }
+}
+
+fn default_players() -> Vec<PlayerProfile> {
+    vec![PlayerProfile::for_seat(0), PlayerProfile::for_seat(1)]
+}
+
+fn default_user_color() -> u32 {
+    PLAYER_COLORS[0]
+}
+
+fn default_grid_size() -> u32 {
+    10
+}
+
+// Board difficulty, presets serialise as their name so older "Normal" saves still load
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Custom(BoardProfile),
+}
+
+// Parameters that shape the arrows generated for a board
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BoardProfile {
+    pub density: f32,     // Arrows per square (0.08 gives 8 arrows on a 10x10 grid)
+    pub snake_ratio: f32, // Fraction of the arrows that lead down
+    pub min_rows: u32,    // Shortest arrow, in board rows
+    pub max_rows: u32,    // Longest arrow, in board rows
+}
+
+impl Difficulty {
+    pub const PRESETS: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn profile(&self) -> BoardProfile {
+        match self {
+            Difficulty::Easy => BoardProfile {
+                density: 0.08,
+                snake_ratio: 0.3,
+                min_rows: 1,
+                max_rows: 3,
+            },
+            Difficulty::Normal => BoardProfile {
+                density: 0.08,
+                snake_ratio: 0.5,
+                min_rows: 1,
+                max_rows: 5,
+            },
+            Difficulty::Hard => BoardProfile {
+                density: 0.12,
+                snake_ratio: 0.7,
+                min_rows: 2,
+                max_rows: 7,
+            },
+            Difficulty::Custom(profile) => *profile,
+        }
+    }
+
+    // Next preset when cycling through difficulties in the control panel,
+    // a custom difficulty is picked and shaped on the settings screen
+    pub fn next(&self) -> Self {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard | Difficulty::Custom(_) => Difficulty::Easy,
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Custom(_) => "Custom",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 // Main game data container
@@ -23,9 +300,15 @@ impl Default for GameSettings {
     fn default() -> Self {
         Self {
             game_id: BigInt::from(0),
-            difficulty: "Normal".to_string(),
+            difficulty: Difficulty::Normal,
             sound_enabled: true,
             max_players: 4,
+            players: default_players(),
+            grid_size: default_grid_size(),
+            rules: RuleSet::default(),
+            board_seed: BoardSeed::Random,
+            seed_proof: None,
+            vdf: VdfConfig::default(),
         }
     }
 }
@@ -36,6 +319,71 @@ impl User {
             user_id,
             name,
             position,
+            skip_turns: 0,
+            color: PLAYER_COLORS[user_id as usize % PLAYER_COLORS.len()],
+            controller: Controller::Human,
+            rerolls: 0,
+            shields: 0,
+        }
+    }
+
+    pub fn add_reward(&mut self, reward: Reward) {
+        match reward {
+            Reward::Reroll => self.rerolls += 1,
+            Reward::SnakeShield => self.shields += 1,
+        }
+    }
+}
+
+// What a VDF result found by digging is worth in the game
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reward {
+    Reroll,      // Roll an extra die on a turn and move by the one you pick
+    SnakeShield, // The next snake landed on is ignored
+}
+
+impl fmt::Display for Reward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reward::Reroll => write!(f, "re-roll token"),
+            Reward::SnakeShield => write!(f, "snake shield"),
+        }
+    }
+}
+
+// Effects that can be attached to a board square and are applied after landing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquareEffect {
+    SkipTurn,
+    RollAgain,
+    SwapWithLeader,
+    Teleport(u32), // Destination square
+    Safe,
+    Bonus(u32), // Number of extra squares to advance
+}
+
+impl SquareEffect {
+    // Symbol drawn on the square (all present in DejaVuSans)
+    pub fn icon(&self) -> char {
+        match self {
+            SquareEffect::SkipTurn => '⚓',
+            SquareEffect::RollAgain => '↻',
+            SquareEffect::SwapWithLeader => '⇄',
+            SquareEffect::Teleport(_) => '✈',
+            SquareEffect::Safe => '☂',
+            SquareEffect::Bonus(_) => '★',
+        }
+    }
+
+    // Fill colour for the square (format: 0xRRGGBBAA)
+    pub fn color(&self) -> u32 {
+        match self {
+            SquareEffect::SkipTurn => 0x777777AF,
+            SquareEffect::RollAgain => 0xFFCC00AF,
+            SquareEffect::SwapWithLeader => 0xFF7700AF,
+            SquareEffect::Teleport(_) => 0x7700FFAF,
+            SquareEffect::Safe => 0xFFFFFFFF,
+            SquareEffect::Bonus(_) => 0x00DD66AF,
         }
     }
 }
@@ -2,6 +2,9 @@ pub mod entities;
 pub mod shapes;
 
 // Re-export all object types
-pub use entities::{GameSettings, User};
+pub use entities::{
+    BoardProfile, BoardSeed, Bot, Controller, Difficulty, GameSettings, Overshoot, PLAYER_COLORS,
+    PlayerProfile, Reward, RuleSet, SquareEffect, User,
+};
 pub use shapes::{Arrow, GameSquare, Png};
 //pub use shapes::{Arrow, GameSquare};
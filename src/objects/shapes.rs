@@ -4,6 +4,7 @@ use image::DynamicImage;
 use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
 
 use crate::drawable::Drawable;
+use crate::objects::SquareEffect;
 
 // Load player images
 macro_rules! load_images {
@@ -23,6 +24,7 @@ pub struct GameSquare {
     pub size: f32,
     pub color: u32, // ARGB format
     pub label: String,
+    pub effect: Option<SquareEffect>,
 }
 
 impl GameSquare {
@@ -34,9 +36,14 @@ impl GameSquare {
             size,
             color,
             label,
+            effect: None,
         }
     }
 
+    pub fn set_effect(&mut self, effect: SquareEffect) {
+        self.effect = Some(effect);
+    }
+
     pub fn contains_point(&self, px: f64, py: f64) -> bool {
         let px = px as f32;
         let py = py as f32;
@@ -44,6 +51,20 @@ impl GameSquare {
         px >= self.x && px < self.x + self.size && py >= self.y && py < self.y + self.size
     }
 
+    // Covers the square with a see-through colour in 0xRRGGBBAA format
+    pub fn draw_tint(&self, pixmap: &mut Pixmap, color: u32) {
+        if let Some(rect) = tiny_skia::Rect::from_xywh(self.x, self.y, self.size, self.size) {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(
+                (color >> 24) as u8,
+                (color >> 16) as u8,
+                (color >> 8) as u8,
+                color as u8,
+            );
+            pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+    }
+
     pub fn center(&self) -> (f32, f32) {
         (
             self.x + (self.size as f32 * 0.5),
@@ -78,11 +99,14 @@ impl Drawable for GameSquare {
         //    Rect::from_ltrb(self.x, self.y, (self.x + self.size), (self.y + self.size)).unwrap(),
         //);
 
+        // Special squares get their own fill so they stand out from the regular pattern
+        let color = self.effect.map_or(self.color, |effect| effect.color());
+
         // Extract RGBA components from u32 color (format: 0xRRGGBBAA)
-        let r = ((self.color >> 24) & 0xFF) as f32 / 255.0;
-        let g = ((self.color >> 16) & 0xFF) as f32 / 255.0;
-        let b = ((self.color >> 8) & 0xFF) as f32 / 255.0;
-        let a = (self.color & 0xFF) as f32 / 255.0;
+        let r = ((color >> 24) & 0xFF) as f32 / 255.0;
+        let g = ((color >> 16) & 0xFF) as f32 / 255.0;
+        let b = ((color >> 8) & 0xFF) as f32 / 255.0;
+        let a = (color & 0xFF) as f32 / 255.0;
 
         // Set up paint for the square fill
         let mut fill_paint = Paint::default();
@@ -126,34 +150,21 @@ impl Drawable for GameSquare {
         let text_x = self.x + self.size * 0.1;
         let text_y = self.y + self.size * 0.9;
 
-        // Create a path for the text
         let mut text_path = PathBuilder::new();
-        let mut current_x = text_x;
-
-        for c in text.chars() {
-            let glyph_id = font.glyph_id(c);
-            let glyph = Glyph {
-                id: glyph_id,
-                scale: PxScale::from(text_size),
-                position: ab_glyph::point(current_x, text_y + 1.0),
-            };
-
-            if let Some(outlined) = font.outline_glyph(glyph) {
-                // Convert the glyph outline to a tiny_skia path
-                outlined.draw(|x, y, coverage| {
-                    if coverage > 0.5 {
-                        let px = x as f32 + outlined.px_bounds().min.x;
-                        let py = y as f32 + outlined.px_bounds().min.y;
-
-                        // Create a small rectangle for each pixel of the glyph
-                        text_path.push_rect(tiny_skia::Rect::from_xywh(px, py, 1.0, 1.0).unwrap());
-                    }
-                });
-
-                //current_x += font.h_advance(glyph_id) * text_size / font.units_per_em();
-                current_x += font.h_advance_unscaled(glyph_id) * text_size
-                    / font.units_per_em().unwrap() as f32;
-            }
+        push_text(&mut text_path, &font, text, text_x, text_y, text_size);
+
+        // Icon for the square effect in the top right corner
+        if let Some(effect) = self.effect {
+            let icon_font = FontArc::try_from_slice(font_data[0]).expect("Failed to load font");
+            let icon_size = self.size * 0.45;
+            push_text(
+                &mut text_path,
+                &icon_font,
+                &effect.icon().to_string(),
+                self.x + self.size * 0.5,
+                self.y + icon_size,
+                icon_size,
+            );
         }
 
         if let Some(text_path) = text_path.finish() {
@@ -223,6 +234,34 @@ impl Drawable for GameSquare {
     */
 }
 
+// Adds the glyph pixels of the text to the path, starting at the given baseline position
+pub fn push_text(path: &mut PathBuilder, font: &FontArc, text: &str, x: f32, y: f32, size: f32) {
+    let mut current_x = x;
+
+    for c in text.chars() {
+        let glyph_id = font.glyph_id(c);
+        let glyph = Glyph {
+            id: glyph_id,
+            scale: PxScale::from(size),
+            position: ab_glyph::point(current_x, y + 1.0),
+        };
+
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            // Convert the glyph outline to a tiny_skia path
+            outlined.draw(|gx, gy, coverage| {
+                if coverage > 0.5 {
+                    let px = gx as f32 + outlined.px_bounds().min.x;
+                    let py = gy as f32 + outlined.px_bounds().min.y;
+
+                    // Create a small rectangle for each pixel of the glyph
+                    path.push_rect(tiny_skia::Rect::from_xywh(px, py, 1.0, 1.0).unwrap());
+                }
+            });
+        }
+        current_x += font.h_advance_unscaled(glyph_id) * size / font.units_per_em().unwrap();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Arrow {
     pub start_x: f32,
@@ -0,0 +1,227 @@
+use tiny_skia::{Color, Pixmap};
+
+use crate::action::ScreenAction;
+use crate::drawable::Drawable;
+use crate::game_controls::{Button, ColorPicker, Dropdown, TextField, draw_text};
+use crate::layout::{Bounds, Direction, Item, Stack};
+use crate::objects::{Controller, PLAYER_COLORS, PlayerProfile};
+
+// Screen for entering player names and picking token colours
+#[derive(Debug, Clone)]
+pub struct PlayersScreen {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    row_height: f32,
+    names: Vec<TextField>,
+    colors: Vec<ColorPicker>,
+    controllers: Vec<Dropdown>,
+    buttons: Vec<Button<ScreenAction>>,
+}
+
+impl PlayersScreen {
+    pub fn new(players: &[PlayerProfile]) -> Self {
+        let names = players
+            .iter()
+            .enumerate()
+            .map(|(indx, profile)| {
+                TextField::new(
+                    format!("Player {} name", indx + 1),
+                    profile.name.clone(),
+                    16,
+                )
+            })
+            .collect();
+
+        let colors = players
+            .iter()
+            .map(|profile| {
+                let selected = PLAYER_COLORS
+                    .iter()
+                    .position(|color| *color == profile.color)
+                    .unwrap_or(0);
+                ColorPicker::new("Colour".to_string(), PLAYER_COLORS.to_vec(), selected)
+            })
+            .collect();
+
+        let controllers = players
+            .iter()
+            .map(|profile| {
+                let selected = Controller::ALL
+                    .iter()
+                    .position(|controller| *controller == profile.controller)
+                    .unwrap_or(0);
+                Dropdown::new(
+                    "Played by".to_string(),
+                    Controller::ALL.iter().map(|c| c.to_string()).collect(),
+                    selected,
+                )
+            })
+            .collect();
+
+        PlayersScreen {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            row_height: 0.0,
+            names,
+            colors,
+            controllers,
+            buttons: vec![
+                Button::new("Done".to_string(), 0x00AA00FF, ScreenAction::Apply),
+                Button::new("Cancel".to_string(), 0xCC0000FF, ScreenAction::Cancel),
+            ],
+        }
+    }
+
+    // Lays out name, colour and controller rows per player, called before every draw
+    pub fn configure(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+
+        // Rows shrink when there are too many players to fit
+        let players = self.names.len() as f32;
+        let row_height = height / (players * 4.1 + 5.0).max(18.0);
+        self.row_height = row_height;
+        let row_x = x + width * 0.1;
+        let row_width = width * 0.8;
+
+        // Name, colour and controller rows for each player with a gap after them,
+        // then the buttons
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_spacing(row_height * 0.25);
+        for _ in &self.names {
+            for _ in 0..3 {
+                stack.add(Item::fixed(row_height));
+            }
+            stack.add(Item::fixed(row_height * 0.125));
+        }
+        let button_row = stack.add(Item::fixed(row_height));
+        let placed = stack.layout(Bounds::new(
+            row_x,
+            y + row_height * 2.0,
+            row_width,
+            height - row_height * 2.0,
+        ));
+
+        for (((name, color), controller), rows) in self
+            .names
+            .iter_mut()
+            .zip(self.colors.iter_mut())
+            .zip(self.controllers.iter_mut())
+            .zip(placed.chunks(4))
+        {
+            name.set_bounds(rows[0].x, rows[0].y, rows[0].width, rows[0].height);
+            color.set_bounds(rows[1].x, rows[1].y, rows[1].width, rows[1].height);
+            controller.set_bounds(rows[2].x, rows[2].y, rows[2].width, rows[2].height);
+        }
+
+        let mut row = Stack::new(Direction::Horizontal);
+        row.set_spacing(row_width * 0.05);
+        for _ in &self.buttons {
+            row.add(Item::fixed(row_width * 0.3));
+        }
+        let placed = row.layout(placed[button_row]);
+        for (button, bounds) in self.buttons.iter_mut().zip(placed) {
+            button.set_bounds(bounds);
+        }
+    }
+
+    // Returns what the clicked button does (Apply or Cancel), if any
+    pub fn onclick(&mut self, x: f64, y: f64) -> Option<ScreenAction> {
+        // An open list sits on top of the other widgets, so it gets the click first
+        if let Some(controller) = self.controllers.iter_mut().find(|c| c.open) {
+            controller.onclick(x, y);
+            return None;
+        }
+
+        for name in &mut self.names {
+            name.onclick(x, y);
+        }
+        for color in &mut self.colors {
+            if color.onclick(x, y) {
+                return None;
+            }
+        }
+        for controller in &mut self.controllers {
+            if controller.onclick(x, y) {
+                return None;
+            }
+        }
+
+        for button in &self.buttons {
+            if button.contains_point(x, y) {
+                return Some(button.action);
+            }
+        }
+        None
+    }
+
+    // Typed text goes to the focused name field
+    pub fn insert_text(&mut self, text: &str) {
+        if let Some(name) = self.names.iter_mut().find(|name| name.focused) {
+            name.insert_text(text);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(name) = self.names.iter_mut().find(|name| name.focused) {
+            name.backspace();
+        }
+    }
+
+    pub fn to_profiles(&self) -> Vec<PlayerProfile> {
+        self.names
+            .iter()
+            .zip(&self.colors)
+            .zip(&self.controllers)
+            .enumerate()
+            .map(|(indx, ((name, color), controller))| {
+                let name = name.text.trim();
+                let name = if name.is_empty() {
+                    format!("Player {}", indx + 1)
+                } else {
+                    name.to_string()
+                };
+                PlayerProfile::new(
+                    name,
+                    color.selected_color(),
+                    Controller::ALL[controller.selected],
+                )
+            })
+            .collect()
+    }
+}
+
+impl Drawable for PlayersScreen {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        pixmap.fill(Color::from_rgba8(235, 235, 235, 255));
+
+        let row_height = self.row_height;
+        draw_text(
+            pixmap,
+            "Players",
+            self.x + self.width * 0.1,
+            self.y + row_height * 1.4,
+            row_height,
+            0x000000FF,
+        );
+
+        for (name, color) in self.names.iter().zip(&self.colors) {
+            name.draw(pixmap);
+            color.draw(pixmap);
+        }
+        for button in &self.buttons {
+            button.draw(pixmap);
+        }
+
+        // Dropdowns last, bottom up, so an open list covers the rows below it
+        for controller in self.controllers.iter().rev() {
+            controller.draw(pixmap);
+        }
+    }
+}
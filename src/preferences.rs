@@ -0,0 +1,29 @@
+use crate::objects::PlayerProfile;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+
+pub const PREFERENCES_FILE: &str = "preferences.json";
+
+// User preferences kept between sessions, separate from saved games
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Preferences {
+    #[serde(default)]
+    pub players: Vec<PlayerProfile>,
+}
+
+impl Preferences {
+    pub fn save_to_file(&self, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let json_string = serde_json::to_string_pretty(self)?;
+        let mut file = fs::File::create(filename)?;
+        file.write_all(json_string.as_bytes())?;
+        println!("Preferences saved to {}", filename);
+        Ok(())
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let json_string = fs::read_to_string(filename)?;
+        let preferences: Self = serde_json::from_str(&json_string)?;
+        Ok(preferences)
+    }
+}
@@ -0,0 +1,573 @@
+use crate::action::Action;
+use crate::heatmap;
+use crate::keymap::Keymap;
+use crate::{Button, GameBoard, GameControls, GameState, Png};
+use image::RgbaImage;
+use std::cmp;
+use std::error::Error;
+use std::path::Path;
+use tiny_skia::{Color, Paint, PathBuilder, Pixmap, Stroke, Transform};
+
+// The buttons next to the board, a human picking between dice gets one per die.
+// Buttons with a keyboard shortcut show its key. The dice buttons are disabled while
+// someone else is moving and once the game is won.
+pub fn control_buttons(
+    state: &GameState,
+    keymap: &Keymap,
+    local_turn: bool,
+    mining: bool,
+) -> Vec<Button> {
+    let mut button_list = Vec::new();
+    if state.pending_dice.is_empty() {
+        button_list.push(Button::new("Roll".to_string(), 0x00CC00FF, Action::Roll));
+        // A re-roll token can be spent before rolling
+        let current = &state.users[state.current_player];
+        if current.rerolls > 0 && !state.extra_die && local_turn {
+            button_list.push(Button::new(
+                "Re-roll".to_string(),
+                0x00AA88FF,
+                Action::Reroll,
+            ));
+        }
+    } else {
+        for die in &state.pending_dice {
+            button_list.push(Button::new(
+                format!("Use {}", die),
+                0x00CC00FF,
+                Action::ChooseDie(*die),
+            ));
+        }
+    }
+    let can_move = local_turn && state.winner.is_none();
+    for button in &mut button_list {
+        button.set_enabled(can_move);
+    }
+    let dig = if mining {
+        Button::new("Stop".to_string(), 0x770000FF, Action::Dig)
+    } else {
+        Button::new("Dig".to_string(), 0xCC0000FF, Action::Dig)
+    };
+    button_list.push(dig);
+    button_list.extend([
+        Button::new("Reset".to_string(), 0x0000CCFF, Action::Reset),
+        Button::new(
+            state.settings.difficulty.to_string(),
+            0xAA6600FF,
+            Action::Difficulty,
+        ),
+        Button::new("Players".to_string(), 0x9030C0FF, Action::Players),
+        Button::new("Settings".to_string(), 0x555555FF, Action::Settings),
+        Button::new("Network".to_string(), 0x007799FF, Action::Network),
+        Button::new("Heatmap".to_string(), 0xDD5500FF, Action::Heatmap),
+    ]);
+    for button in &mut button_list {
+        button.set_hint(keymap.hint(button.action));
+    }
+    button_list
+}
+
+// Draws the board, the controls and the tokens into a frame of 0xAARRGGBB pixels
+pub fn draw_game(
+    state: &mut GameState,
+    game_board: &mut GameBoard,
+    game_controls: &mut GameControls,
+    button_list: Vec<Button>,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or_else(|| format!("Can't draw a {}x{} picture", width, height))?;
+
+    // Clear the pixmap with a white background
+    pixmap.fill(Color::from_rgba8(255, 255, 255, 255));
+
+    // The board takes 90% of the smaller dimension, the controls get what is left beside it
+    let board_size = cmp::min(width, height) as f32 * 0.9;
+    let board_padding = cmp::min(width, height) as f32 * 0.1;
+    let grid_count = state.grid_size as f32;
+    let spacing = (board_size / grid_count) * 0.1;
+    let sq_size = (board_size / grid_count) - (spacing * 2.0);
+
+    game_board.init(
+        board_padding as i32,
+        board_size as i32,
+        grid_count as i32,
+        spacing as i32,
+        state.colors.clone(),
+        state.arrows.as_mut(),
+        &state.special_squares,
+    );
+
+    game_controls.configure(
+        board_size + board_padding,
+        board_padding,
+        cmp::min(
+            (width as f32 - board_size - (board_padding * 2.0)) as i32,
+            (sq_size * 3.0) as i32,
+        )
+        .max(0) as f32,
+        height as f32 - (board_padding * 2.0),
+        0xCCCCCC0F,
+        "The Game".to_string(),
+        sq_size / 2.0,
+        button_list,
+    );
+    game_controls.set_players(&state.users, state.current_player, state.winner);
+    game_controls.board_info.update(state);
+
+    // Squares tinted by how often tokens end up there, while the overlay is on
+    let heat = heatmap::square_heat(
+        game_controls.heatmap.source,
+        state,
+        game_controls.board_info.analysis.as_ref(),
+    );
+    game_controls.heatmap.busiest = heat
+        .as_ref()
+        .map_or(0.0, |heat| heat.iter().copied().fold(0.0, f64::max));
+
+    game_board.draw_with_heat(&mut pixmap, heat.as_deref());
+    game_controls.draw(&mut pixmap);
+
+    // Where each player's token goes
+    let token_positions: Vec<(f32, f32)> = (0..state.users.len())
+        .map(|player_num| {
+            let position = state.users[player_num].position;
+            let mut player_position =
+                get_sq_center(game_board, position.saturating_sub(1) as usize)
+                    .unwrap_or((0.0, 0.0));
+
+            // Players yet to move wait to the left of the start square
+            if position == 0 {
+                player_position.0 =
+                    player_position.0 - sq_size + (player_num as f32 * (sq_size / 5.0));
+                player_position.1 -= player_num as f32 * (sq_size / 4.0);
+            }
+            player_position
+        })
+        .collect();
+
+    // A ring in the player's colour tells tokens with the same image apart
+    for (user, position) in state.users.iter().zip(&token_positions) {
+        draw_token_marker(&mut pixmap, *position, sq_size * 0.4, user.color);
+    }
+
+    let mut frame = vec![0; (width * height) as usize];
+    copy_pixmap_to_buffer(&pixmap, &mut frame);
+
+    for (player_num, player_position) in token_positions.iter().enumerate() {
+        // There are two token images, further players reuse them
+        let player = Png::new(player_num as i32, player_num % 2);
+
+        player.draw_png_scaled_height(
+            &mut frame,
+            width,
+            (player_position.0 - sq_size / 2.0) as i32,
+            (player_position.1 - sq_size / 2.0) as i32,
+            (sq_size * 0.9) as u32,
+            !get_range_flag(state.users[player_num].position.max(1), grid_count as u32),
+        );
+    }
+
+    Ok(frame)
+}
+
+// Renders a game without a window, as the window would show it at this size
+pub fn render(state: &GameState, width: u32, height: u32) -> Result<RgbaImage, Box<dyn Error>> {
+    // Layout fills in the board's arrows, so work on a copy
+    let mut state = state.clone();
+    let mut game_board = GameBoard::SquareBoard {
+        squares: vec![],
+        arrows: vec![],
+    };
+    let mut game_controls = GameControls::new();
+    let button_list = control_buttons(&state, &Keymap::default(), !state.ai_to_move(), false);
+
+    let frame = draw_game(
+        &mut state,
+        &mut game_board,
+        &mut game_controls,
+        button_list,
+        width,
+        height,
+    )?;
+    Ok(frame_to_image(&frame, width, height))
+}
+
+pub fn frame_to_image(frame: &[u32], width: u32, height: u32) -> RgbaImage {
+    let mut bytes = Vec::with_capacity(frame.len() * 4);
+    for pixel in frame {
+        bytes.extend([
+            (pixel >> 16) as u8,
+            (pixel >> 8) as u8,
+            *pixel as u8,
+            (pixel >> 24) as u8,
+        ]);
+    }
+    RgbaImage::from_raw(width, height, bytes).unwrap()
+}
+
+pub fn save_png(image: &RgbaImage, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    image.save_with_format(path, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+pub fn copy_pixmap_to_buffer(pixmap: &Pixmap, buffer: &mut [u32]) {
+    for (i, pixel) in pixmap.pixels().iter().enumerate() {
+        let r = pixel.red();
+        let g = pixel.green();
+        let b = pixel.blue();
+        let a = pixel.alpha();
+        buffer[i] = ((a as u32) << 24) | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32);
+    }
+}
+
+fn get_sq_center(board: &GameBoard, sq_number: usize) -> Option<(f32, f32)> {
+    if let GameBoard::SquareBoard { squares, .. } = board
+        && let Some(square) = squares.get(sq_number)
+    {
+        return Some(square.center());
+    }
+    None
+}
+
+// TODO: Make this generalized for any range not just a grid of 10
+/*
+fn get_range_flag(n: u32) -> bool {
+    if n < 1 {
+        return false;
+    } // Handle invalid input (optional)
+    let mod_value = (n - 1) % 20;
+    mod_value < 10
+}
+*/
+
+fn get_range_flag(n: u32, range_size: u32) -> bool {
+    if n < 1 || range_size < 1 {
+        return false;
+    } // Handle invalid input
+    let mod_value = (n - 1) % (2 * range_size);
+    mod_value < range_size
+}
+
+fn draw_token_marker(pixmap: &mut Pixmap, center: (f32, f32), radius: f32, color: u32) {
+    if let Some(circle) = PathBuilder::from_circle(center.0, center.1, radius) {
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(
+            (color >> 24) as u8,
+            (color >> 16) as u8,
+            (color >> 8) as u8,
+            color as u8,
+        );
+        paint.anti_alias = true;
+
+        pixmap.stroke_path(
+            &circle,
+            &paint,
+            &Stroke {
+                width: radius * 0.25,
+                ..Default::default()
+            },
+            Transform::identity(),
+            None,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Drawable;
+    use crate::game_controls::ButtonState;
+    use crate::heatmap::HeatSource;
+    use crate::objects::Bot;
+    use crate::objects::SquareEffect;
+    use crate::simulation;
+    use curv::BigInt;
+    use std::path::PathBuf;
+
+    // Reference pictures live here, run with UPDATE_GOLDEN=1 to rewrite them
+    const GOLDEN_DIR: &str = "tests/golden";
+    // Channels may be off by this much before a pixel counts as different
+    const CHANNEL_TOLERANCE: u8 = 8;
+    // Share of pixels that may differ, for small changes in anti-aliasing
+    const PIXEL_TOLERANCE: f64 = 0.002;
+
+    fn image_of(pixmap: &Pixmap) -> RgbaImage {
+        let mut frame = vec![0; (pixmap.width() * pixmap.height()) as usize];
+        copy_pixmap_to_buffer(pixmap, &mut frame);
+        frame_to_image(&frame, pixmap.width(), pixmap.height())
+    }
+
+    fn assert_golden(name: &str, image: &RgbaImage) {
+        let path = PathBuf::from(GOLDEN_DIR).join(format!("{}.png", name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+            save_png(image, &path).unwrap();
+            return;
+        }
+
+        let golden = image::open(&path)
+            .unwrap_or_else(|e| panic!("{}: {}, run with UPDATE_GOLDEN=1", path.display(), e))
+            .to_rgba8();
+        assert_eq!(golden.dimensions(), image.dimensions(), "{} size", name);
+
+        let different = golden
+            .pixels()
+            .zip(image.pixels())
+            .filter(|(a, b)| {
+                a.0.iter()
+                    .zip(b.0)
+                    .any(|(x, y)| x.abs_diff(y) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        let share = different as f64 / (image.width() * image.height()) as f64;
+        if share > PIXEL_TOLERANCE {
+            // Keep what was drawn so it can be compared by eye
+            let actual = std::env::temp_dir().join(format!("{}.actual.png", name));
+            save_png(image, &actual).unwrap();
+            panic!(
+                "{} differs from {} in {} pixels, drawn picture saved to {}",
+                name,
+                path.display(),
+                different,
+                actual.display()
+            );
+        }
+    }
+
+    // A game in play: tokens off the start square, one of them on the far row
+    fn game_in_play(seed: u32) -> GameState {
+        let mut state = GameState::with_seed(BigInt::from(seed));
+        state.new_game = false;
+        state.users[0].position = 17;
+        state.users[1].position = 23;
+        state.current_player = 1;
+        state
+    }
+
+    #[test]
+    fn test_renders_without_a_window() {
+        let state = GameState::new();
+        let image = render(&state, 640, 480).unwrap();
+        assert_eq!(image.dimensions(), (640, 480));
+
+        // The background is white and the board is drawn over it
+        assert_eq!(image.get_pixel(0, 0).0, [255, 255, 255, 255]);
+        assert!(image.pixels().any(|pixel| pixel.0 != [255, 255, 255, 255]));
+
+        // The same game draws the same picture
+        assert_eq!(render(&state, 640, 480).unwrap(), image);
+    }
+
+    #[test]
+    fn test_golden_board() {
+        let mut state = GameState::with_seed(BigInt::from(1));
+        state.special_squares = vec![
+            (12, SquareEffect::RollAgain),
+            (45, SquareEffect::SwapWithLeader),
+        ];
+
+        let mut game_board = GameBoard::SquareBoard {
+            squares: vec![],
+            arrows: vec![],
+        };
+        game_board.init(
+            40,
+            360,
+            10,
+            3,
+            state.colors.clone(),
+            &mut state.arrows,
+            &state.special_squares,
+        );
+
+        let mut pixmap = Pixmap::new(440, 440).unwrap();
+        pixmap.fill(Color::WHITE);
+        game_board.draw(&mut pixmap);
+        assert_golden("board_seed_1", &image_of(&pixmap));
+    }
+
+    #[test]
+    fn test_golden_controls() {
+        let mut state = game_in_play(2);
+        state.users[0].rerolls = 2;
+        state.users[1].shields = 1;
+        state.pending_dice = vec![3, 5];
+
+        let mut game_controls = GameControls::new();
+        game_controls.configure(
+            10.0,
+            10.0,
+            180.0,
+            380.0,
+            0xCCCCCC0F,
+            "The Game".to_string(),
+            16.0,
+            control_buttons(&state, &Keymap::default(), true, true),
+        );
+        game_controls.set_players(&state.users, state.current_player, state.winner);
+        game_controls.board_info.update(&state);
+
+        let mut pixmap = Pixmap::new(200, 400).unwrap();
+        pixmap.fill(Color::WHITE);
+        game_controls.draw(&mut pixmap);
+        assert_golden("controls", &image_of(&pixmap));
+    }
+
+    #[test]
+    fn test_controls_fit_a_short_panel() {
+        let state = GameState::with_seed(BigInt::from(1));
+        let mut pixmap = Pixmap::new(200, 400).unwrap();
+        for height in [380.0, 260.0] {
+            let mut game_controls = GameControls::new();
+            game_controls.configure(
+                10.0,
+                10.0,
+                180.0,
+                height,
+                0xCCCCCC0F,
+                "The Game".to_string(),
+                16.0,
+                control_buttons(&state, &Keymap::default(), true, false),
+            );
+            game_controls.set_players(&state.users, state.current_player, state.winner);
+            game_controls.draw(&mut pixmap);
+
+            // Buttons squeeze before the player list falls off the bottom
+            let list = &game_controls.player_list;
+            let list_bottom = list.y + list.row_height * list.users.len() as f32;
+            assert!(list_bottom <= 10.0 + height);
+            let button = &game_controls.buttons[0];
+            assert!(button.end_y - button.start_y >= 16.0 * 0.6 - 0.01);
+        }
+    }
+
+    #[test]
+    fn test_controls_skip_a_panel_with_no_room() {
+        let state = GameState::with_seed(BigInt::from(1));
+        let mut pixmap = Pixmap::new(200, 400).unwrap();
+        pixmap.fill(Color::WHITE);
+        for width in [0.0, -120.0] {
+            let mut game_controls = GameControls::new();
+            game_controls.configure(
+                10.0,
+                10.0,
+                width,
+                380.0,
+                0xCCCCCC0F,
+                "The Game".to_string(),
+                16.0,
+                control_buttons(&state, &Keymap::default(), true, false),
+            );
+            game_controls.set_players(&state.users, state.current_player, state.winner);
+            game_controls.draw(&mut pixmap);
+            assert_eq!(game_controls.width, 0.0);
+        }
+        assert!(
+            pixmap
+                .pixels()
+                .iter()
+                .all(|pixel| pixel.demultiply() == Color::WHITE.to_color_u8())
+        );
+    }
+
+    #[test]
+    fn test_buttons_click_on_release() {
+        let mut state = GameState::with_seed(BigInt::from(1));
+        let mut game_controls = GameControls::new();
+        let mut pixmap = Pixmap::new(200, 400).unwrap();
+        let mut layout = |game_controls: &mut GameControls, state: &GameState| {
+            game_controls.configure(
+                10.0,
+                10.0,
+                180.0,
+                380.0,
+                0xCCCCCC0F,
+                "The Game".to_string(),
+                16.0,
+                control_buttons(state, &Keymap::default(), true, false),
+            );
+            game_controls.draw(&mut pixmap);
+        };
+        layout(&mut game_controls, &state);
+        let roll = game_controls.buttons[0].clone();
+        let (x, y) = (roll.start_x as f64 + 5.0, roll.start_y as f64 + 5.0);
+
+        assert!(game_controls.pointer_moved(x, y));
+        assert!(!game_controls.pointer_moved(x + 1.0, y));
+        layout(&mut game_controls, &state);
+        assert_eq!(game_controls.buttons[0].state, ButtonState::Hovered);
+
+        // Nothing happens until the mouse comes up, and only if it is still on the button
+        assert!(game_controls.press(x, y));
+        layout(&mut game_controls, &state);
+        assert_eq!(game_controls.buttons[0].state, ButtonState::Pressed);
+        assert_eq!(game_controls.release(x, y), Some(Action::Roll));
+        assert!(game_controls.press(x, y));
+        assert_eq!(game_controls.release(x, y + 100.0), None);
+
+        // Roll is greyed out and ignores the mouse once the game is over
+        state.winner = Some(0);
+        layout(&mut game_controls, &state);
+        assert_eq!(game_controls.buttons[0].state, ButtonState::Disabled);
+        assert!(!game_controls.press(x, y));
+        assert_eq!(game_controls.release(x, y), None);
+    }
+
+    #[test]
+    fn test_golden_heatmap() {
+        // A few turns in so both sources have something to show
+        let mut state = simulation::new_game(&BigInt::from(6), 2, Bot::Greedy);
+        for turn in 0..20 {
+            state.take_turn_with_seed([turn; 32]);
+        }
+
+        for (name, source) in [
+            ("heatmap_played", HeatSource::Played),
+            ("heatmap_expected", HeatSource::Expected),
+        ] {
+            let mut game_board = GameBoard::SquareBoard {
+                squares: vec![],
+                arrows: vec![],
+            };
+            let mut game_controls = GameControls::new();
+            game_controls.heatmap.source = source;
+            let frame = draw_game(
+                &mut state.clone(),
+                &mut game_board,
+                &mut game_controls,
+                control_buttons(&state, &Keymap::default(), false, false),
+                480,
+                320,
+            )
+            .unwrap();
+            assert_golden(name, &frame_to_image(&frame, 480, 320));
+        }
+    }
+
+    #[test]
+    fn test_golden_tokens() {
+        assert_golden(
+            "new_game_seed_3",
+            &render(&GameState::with_seed(BigInt::from(3)), 480, 320).unwrap(),
+        );
+        assert_golden("game_seed_4", &render(&game_in_play(4), 480, 320).unwrap());
+    }
+
+    #[test]
+    fn test_portrait_window_leaves_out_the_controls() {
+        // No room beside the board for the control panel
+        assert_golden(
+            "portrait_seed_3",
+            &render(&GameState::with_seed(BigInt::from(3)), 200, 400).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_empty_picture_is_an_error() {
+        let state = GameState::with_seed(BigInt::from(3));
+        assert!(render(&state, 0, 0).is_err());
+        assert!(render(&state, 0, 320).is_err());
+        assert!(render(&state, 1, 1).is_ok());
+    }
+}
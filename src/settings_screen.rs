@@ -0,0 +1,530 @@
+use tiny_skia::{Color, Pixmap};
+
+use crate::action::ScreenAction;
+use crate::drawable::Drawable;
+use crate::game_controls::{Button, Dropdown, Stepper, TextField, Toggle, draw_text};
+use crate::layout::{Bounds, Direction, Item, Stack};
+use crate::objects::{
+    BoardProfile, BoardSeed, Difficulty, GameSettings, Overshoot, PlayerProfile, RuleSet,
+};
+use crate::vdf::{Discriminant, Target, VdfConfig};
+
+// Full window screen for editing the GameSettings used by the next game
+#[derive(Debug, Clone)]
+pub struct SettingsScreen {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    row_height: f32,
+    base: GameSettings, // Values the screen does not edit, like the game id
+    player_count: Stepper,
+    names: Vec<TextField>,
+    grid_size: Stepper,
+    difficulty: Dropdown,
+    arrow_density: Stepper, // Arrows per 100 squares, for a custom difficulty
+    snake_share: Stepper,   // Snakes in every 10 arrows
+    shortest_arrow: Stepper,
+    longest_arrow: Stepper,
+    sound: Toggle,
+    overshoot: Dropdown,
+    special_squares: Toggle,
+    die_sides: Stepper,
+    choose_die: Toggle,
+    board_seed: Dropdown,
+    vdf_input: TextField,
+    vdf_iterations: Stepper, // Power of two
+    fair_min_turns: Stepper,
+    fair_max_turns: Stepper,
+    fair_advantage: Stepper, // Percent
+    dig_discriminant: Dropdown,
+    dig_input: TextField,
+    dig_target: Stepper, // Keep one output in this many, 1 keeps all
+    dig_throttle: Stepper,
+    buttons: Vec<Button<ScreenAction>>,
+}
+
+impl SettingsScreen {
+    pub fn new(settings: &GameSettings) -> Self {
+        let max_players = settings.max_players.max(1);
+
+        // A field for every seat, only the first player_count are shown
+        let names = (0..max_players as usize)
+            .map(|indx| {
+                let name = settings
+                    .players
+                    .get(indx)
+                    .map(|profile| profile.name.clone())
+                    .unwrap_or_else(|| format!("Player {}", indx + 1));
+                TextField::new(format!("Player {} name", indx + 1), name, 16)
+            })
+            .collect();
+
+        // Custom comes after the presets, it starts out shaped like the current difficulty
+        let selected_difficulty = match settings.difficulty {
+            Difficulty::Custom(_) => Difficulty::PRESETS.len(),
+            difficulty => Difficulty::PRESETS
+                .iter()
+                .position(|preset| *preset == difficulty)
+                .unwrap_or(1),
+        };
+        let custom = settings.difficulty.profile();
+
+        let selected_overshoot = Overshoot::ALL
+            .iter()
+            .position(|overshoot| *overshoot == settings.rules.overshoot)
+            .unwrap_or(0);
+
+        let (vdf_input, log_iterations) = match &settings.board_seed {
+            BoardSeed::Vdf {
+                input,
+                log_iterations,
+            } => (input.clone(), *log_iterations),
+            _ => ("Snakes and arrows".to_string(), 8),
+        };
+        let (min_turns, max_turns, max_advantage) = match settings.board_seed {
+            BoardSeed::Fair {
+                min_turns,
+                max_turns,
+                max_advantage,
+            } => (min_turns, max_turns, max_advantage),
+            _ => (25, 40, 3),
+        };
+        let seed_selected = match settings.board_seed {
+            BoardSeed::Random => 0,
+            BoardSeed::Vdf { .. } => 1,
+            BoardSeed::Fair { .. } => 2,
+        };
+
+        // Built-in first, then the sizes a discriminant can be generated at
+        let dig = &settings.vdf;
+        let selected_discriminant = match dig.discriminant {
+            Discriminant::Builtin => 0,
+            Discriminant::Generated { bits } => Discriminant::SIZES
+                .iter()
+                .position(|size| *size == bits)
+                .map_or(0, |indx| indx + 1),
+        };
+        let target_divisor = match dig.target {
+            Target::Every => 1,
+            Target::DivisibleBy(divisor) => divisor,
+        };
+
+        SettingsScreen {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+            row_height: 0.0,
+            base: settings.clone(),
+            player_count: Stepper::new(
+                "Players".to_string(),
+                settings.players.len() as u32,
+                1,
+                max_players,
+            ),
+            names,
+            grid_size: Stepper::new("Grid size".to_string(), settings.grid_size, 5, 15),
+            difficulty: Dropdown::new(
+                "Difficulty".to_string(),
+                Difficulty::PRESETS
+                    .iter()
+                    .map(|d| d.to_string())
+                    .chain(std::iter::once("Custom".to_string()))
+                    .collect(),
+                selected_difficulty,
+            ),
+            arrow_density: Stepper::new(
+                "Custom arrows per 100 squares".to_string(),
+                (custom.density * 100.0).round() as u32,
+                1,
+                30,
+            ),
+            snake_share: Stepper::new(
+                "Custom snakes in 10 arrows".to_string(),
+                (custom.snake_ratio * 10.0).round() as u32,
+                0,
+                10,
+            ),
+            shortest_arrow: Stepper::new(
+                "Custom shortest arrow (rows)".to_string(),
+                custom.min_rows,
+                1,
+                10,
+            ),
+            longest_arrow: Stepper::new(
+                "Custom longest arrow (rows)".to_string(),
+                custom.max_rows,
+                1,
+                19,
+            ),
+            sound: Toggle::new("Sound".to_string(), settings.sound_enabled),
+            overshoot: Dropdown::new(
+                "Overshooting the end".to_string(),
+                Overshoot::ALL.iter().map(|o| o.to_string()).collect(),
+                selected_overshoot,
+            ),
+            special_squares: Toggle::new(
+                "Special squares".to_string(),
+                settings.rules.special_squares,
+            ),
+            die_sides: Stepper::new("Die sides".to_string(), settings.rules.die_sides, 2, 12),
+            choose_die: Toggle::new(
+                "Roll two dice, pick one".to_string(),
+                settings.rules.choose_die,
+            ),
+            board_seed: Dropdown::new(
+                "Board seed".to_string(),
+                vec!["Random".to_string(), "VDF".to_string(), "Fair".to_string()],
+                seed_selected,
+            ),
+            vdf_input: TextField::new("VDF input".to_string(), vdf_input, 40),
+            vdf_iterations: Stepper::new("VDF iterations (2^n)".to_string(), log_iterations, 4, 20),
+            fair_min_turns: Stepper::new("Fair turns, at least".to_string(), min_turns, 1, 200),
+            fair_max_turns: Stepper::new("Fair turns, at most".to_string(), max_turns, 1, 200),
+            fair_advantage: Stepper::new("First player edge (%)".to_string(), max_advantage, 0, 50),
+            dig_discriminant: Dropdown::new(
+                "Dig class group".to_string(),
+                std::iter::once(Discriminant::Builtin)
+                    .chain(
+                        Discriminant::SIZES
+                            .iter()
+                            .map(|bits| Discriminant::Generated { bits: *bits }),
+                    )
+                    .map(|discriminant| discriminant.to_string())
+                    .collect(),
+                selected_discriminant,
+            ),
+            dig_input: TextField::new("Dig input".to_string(), dig.input.clone(), 40),
+            dig_target: Stepper::new("Dig target (1 in n)".to_string(), target_divisor, 1, 100),
+            dig_throttle: Stepper::new(
+                "Dig pause (ms)".to_string(),
+                dig.throttle_ms.min(50) as u32,
+                0,
+                50,
+            ),
+            buttons: vec![
+                Button::new("Apply".to_string(), 0x00AA00FF, ScreenAction::Apply),
+                Button::new("Cancel".to_string(), 0xCC0000FF, ScreenAction::Cancel),
+            ],
+        }
+    }
+
+    // Lays the widgets out in rows, called before every draw like GameControls::configure
+    pub fn configure(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+
+        // Rows shrink when the player names would push the buttons off screen
+        let visible_names = self.player_count.value as usize;
+        let rows = 22 + visible_names;
+        let row_height = height / (rows as f32 * 1.25 + 5.5).max(18.0);
+        self.row_height = row_height;
+        let row_x = x + width * 0.1;
+        let row_width = width * 0.8;
+
+        // One row per widget from the top down, then Apply and Cancel after a gap
+        let widgets = 21 + visible_names;
+        let mut stack = Stack::new(Direction::Vertical);
+        stack.set_spacing(row_height * 0.25);
+        for _ in 0..widgets {
+            stack.add(Item::fixed(row_height));
+        }
+        stack.add(Item::fixed(row_height * 0.375));
+        let button_row = stack.add(Item::fixed(row_height));
+        let placed = stack.layout(Bounds::new(
+            row_x,
+            y + row_height * 2.0,
+            row_width,
+            height - row_height * 2.0,
+        ));
+        let buttons = placed[button_row];
+        let mut rows = placed.into_iter();
+        let mut next_row = || rows.next().unwrap_or_default();
+
+        let row = next_row();
+        self.player_count
+            .set_bounds(row.x, row.y, row.width, row.height);
+        for name in self.names.iter_mut().take(visible_names) {
+            let row = next_row();
+            name.set_bounds(row.x, row.y, row.width, row.height);
+        }
+        let row = next_row();
+        self.grid_size
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.difficulty
+            .set_bounds(row.x, row.y, row.width, row.height);
+        for stepper in [
+            &mut self.arrow_density,
+            &mut self.snake_share,
+            &mut self.shortest_arrow,
+            &mut self.longest_arrow,
+        ] {
+            let row = next_row();
+            stepper.set_bounds(row.x, row.y, row.width, row.height);
+        }
+        let row = next_row();
+        self.sound.set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.overshoot
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.special_squares
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.die_sides
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.choose_die
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.board_seed
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.vdf_input
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.vdf_iterations
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.fair_min_turns
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.fair_max_turns
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.fair_advantage
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.dig_discriminant
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.dig_input
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.dig_target
+            .set_bounds(row.x, row.y, row.width, row.height);
+        let row = next_row();
+        self.dig_throttle
+            .set_bounds(row.x, row.y, row.width, row.height);
+
+        // Apply and Cancel side by side under the last row
+        let mut row = Stack::new(Direction::Horizontal);
+        row.set_spacing(row_width * 0.05);
+        for _ in &self.buttons {
+            row.add(Item::fixed(row_width * 0.3));
+        }
+        let placed = row.layout(buttons);
+        for (button, bounds) in self.buttons.iter_mut().zip(placed) {
+            button.set_bounds(bounds);
+        }
+    }
+
+    // Returns what the clicked button does (Apply or Cancel), if any
+    pub fn onclick(&mut self, x: f64, y: f64) -> Option<ScreenAction> {
+        // An open list sits on top of the other widgets, so it gets the click first
+        for dropdown in [
+            &mut self.difficulty,
+            &mut self.overshoot,
+            &mut self.board_seed,
+            &mut self.dig_discriminant,
+        ] {
+            if dropdown.open {
+                dropdown.onclick(x, y);
+                return None;
+            }
+        }
+
+        let visible_names = self.player_count.value as usize;
+        for name in self.names.iter_mut().take(visible_names) {
+            name.onclick(x, y);
+        }
+        self.vdf_input.onclick(x, y);
+        self.dig_input.onclick(x, y);
+
+        if self.player_count.onclick(x, y)
+            || self.grid_size.onclick(x, y)
+            || self.difficulty.onclick(x, y)
+            || self.arrow_density.onclick(x, y)
+            || self.snake_share.onclick(x, y)
+            || self.shortest_arrow.onclick(x, y)
+            || self.longest_arrow.onclick(x, y)
+            || self.sound.onclick(x, y)
+            || self.overshoot.onclick(x, y)
+            || self.special_squares.onclick(x, y)
+            || self.die_sides.onclick(x, y)
+            || self.choose_die.onclick(x, y)
+            || self.board_seed.onclick(x, y)
+            || self.vdf_iterations.onclick(x, y)
+            || self.fair_min_turns.onclick(x, y)
+            || self.fair_max_turns.onclick(x, y)
+            || self.fair_advantage.onclick(x, y)
+            || self.dig_discriminant.onclick(x, y)
+            || self.dig_target.onclick(x, y)
+            || self.dig_throttle.onclick(x, y)
+        {
+            return None;
+        }
+
+        for button in &self.buttons {
+            if button.contains_point(x, y) {
+                return Some(button.action);
+            }
+        }
+        None
+    }
+
+    // Typed text goes to the focused text field
+    pub fn insert_text(&mut self, text: &str) {
+        if let Some(field) = self.focused_field() {
+            field.insert_text(text);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(field) = self.focused_field() {
+            field.backspace();
+        }
+    }
+
+    fn focused_field(&mut self) -> Option<&mut TextField> {
+        self.names
+            .iter_mut()
+            .chain([&mut self.vdf_input, &mut self.dig_input])
+            .find(|field| field.focused)
+    }
+
+    pub fn to_settings(&self) -> GameSettings {
+        let players = self
+            .names
+            .iter()
+            .take(self.player_count.value as usize)
+            .enumerate()
+            .map(|(indx, field)| {
+                // Seats keep their colour, new seats get the default one
+                let mut profile = self
+                    .base
+                    .players
+                    .get(indx)
+                    .cloned()
+                    .unwrap_or_else(|| PlayerProfile::for_seat(indx));
+                let name = field.text.trim();
+                profile.name = if name.is_empty() {
+                    format!("Player {}", indx + 1)
+                } else {
+                    name.to_string()
+                };
+                profile
+            })
+            .collect();
+
+        // A range the wrong way round is taken as meant
+        let difficulty = Difficulty::PRESETS
+            .get(self.difficulty.selected)
+            .copied()
+            .unwrap_or(Difficulty::Custom(BoardProfile {
+                density: self.arrow_density.value as f32 / 100.0,
+                snake_ratio: self.snake_share.value as f32 / 10.0,
+                min_rows: self.shortest_arrow.value.min(self.longest_arrow.value),
+                max_rows: self.shortest_arrow.value.max(self.longest_arrow.value),
+            }));
+
+        let board_seed = match self.board_seed.selected {
+            1 => BoardSeed::Vdf {
+                input: self.vdf_input.text.trim().to_string(),
+                log_iterations: self.vdf_iterations.value,
+            },
+            // A range the wrong way round is taken as meant
+            2 => BoardSeed::Fair {
+                min_turns: self.fair_min_turns.value.min(self.fair_max_turns.value),
+                max_turns: self.fair_min_turns.value.max(self.fair_max_turns.value),
+                max_advantage: self.fair_advantage.value,
+            },
+            _ => BoardSeed::Random,
+        };
+
+        let discriminant = match self.dig_discriminant.selected {
+            0 => Discriminant::Builtin,
+            indx => Discriminant::Generated {
+                bits: Discriminant::SIZES[indx - 1],
+            },
+        };
+        let target = match self.dig_target.value {
+            1 => Target::Every,
+            divisor => Target::DivisibleBy(divisor),
+        };
+        let vdf = VdfConfig {
+            discriminant,
+            input: self.dig_input.text.trim().to_string(),
+            target,
+            throttle_ms: self.dig_throttle.value as u64,
+        };
+
+        GameSettings {
+            difficulty,
+            sound_enabled: self.sound.value,
+            players,
+            grid_size: self.grid_size.value,
+            rules: RuleSet {
+                overshoot: Overshoot::ALL[self.overshoot.selected],
+                special_squares: self.special_squares.value,
+                die_sides: self.die_sides.value,
+                choose_die: self.choose_die.value,
+            },
+            board_seed,
+            vdf,
+            ..self.base.clone()
+        }
+    }
+}
+
+impl Drawable for SettingsScreen {
+    fn draw(&self, pixmap: &mut Pixmap) {
+        pixmap.fill(Color::from_rgba8(235, 235, 235, 255));
+
+        let row_height = self.row_height;
+        draw_text(
+            pixmap,
+            "Settings",
+            self.x + self.width * 0.1,
+            self.y + row_height * 1.4,
+            row_height,
+            0x000000FF,
+        );
+
+        self.player_count.draw(pixmap);
+        for name in self.names.iter().take(self.player_count.value as usize) {
+            name.draw(pixmap);
+        }
+        self.grid_size.draw(pixmap);
+        self.arrow_density.draw(pixmap);
+        self.snake_share.draw(pixmap);
+        self.shortest_arrow.draw(pixmap);
+        self.longest_arrow.draw(pixmap);
+        self.sound.draw(pixmap);
+        self.special_squares.draw(pixmap);
+        self.die_sides.draw(pixmap);
+        self.choose_die.draw(pixmap);
+        self.vdf_input.draw(pixmap);
+        self.vdf_iterations.draw(pixmap);
+        self.fair_min_turns.draw(pixmap);
+        self.fair_max_turns.draw(pixmap);
+        self.fair_advantage.draw(pixmap);
+        self.dig_input.draw(pixmap);
+        self.dig_target.draw(pixmap);
+        self.dig_throttle.draw(pixmap);
+
+        for button in &self.buttons {
+            button.draw(pixmap);
+        }
+
+        // Dropdowns last so an open list covers the rows below it
+        self.dig_discriminant.draw(pixmap);
+        self.board_seed.draw(pixmap);
+        self.overshoot.draw(pixmap);
+        self.difficulty.draw(pixmap);
+    }
+}
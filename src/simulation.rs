@@ -0,0 +1,221 @@
+// Plays whole games between computer players without a window and collects statistics.
+// Every game is on the board the seed gives, the dice for each turn are drawn from the
+// seed too, so the same seed always plays out the same way.
+use crate::fair_dice::Seed;
+use crate::game_state::{GameEvent, GameState};
+use crate::objects::{Bot, Controller, GameSettings, PlayerProfile};
+use curv::BigInt;
+use curv::arithmetic::Converter;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Games still going after this many turns are given up on
+const MAX_TURNS: u32 = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub seed: BigInt,
+    pub players: usize,
+    pub games: u32,
+    pub bot: Bot,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            seed: GameState::full_seed(BigInt::from(1)),
+            players: 2,
+            games: 100,
+            bot: Bot::Greedy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    pub games: u32,
+    pub finished: u32,
+    pub turns: u64, // Over the finished games
+    pub shortest: Option<u32>,
+    pub longest: Option<u32>,
+    pub rolls: u64,
+    pub ladders: u64,
+    pub snakes: u64,
+    pub shields: u64,
+    pub arrow_hits: BTreeMap<(u32, u32), u64>, // Times each arrow was followed
+    pub wins: Vec<u32>,                        // Games won by each seat
+}
+
+impl Statistics {
+    pub fn average_turns(&self) -> f64 {
+        if self.finished == 0 {
+            return 0.0;
+        }
+        self.turns as f64 / self.finished as f64
+    }
+
+    pub fn win_rate(&self, seat: usize) -> f64 {
+        if self.finished == 0 {
+            return 0.0;
+        }
+        self.wins.get(seat).copied().unwrap_or(0) as f64 / self.finished as f64
+    }
+
+    fn add_game(&mut self, state: &GameState, turns: u32) {
+        self.games += 1;
+        if let Some(winner) = state.winner {
+            self.finished += 1;
+            self.turns += turns as u64;
+            self.shortest = Some(self.shortest.map_or(turns, |n| n.min(turns)));
+            self.longest = Some(self.longest.map_or(turns, |n| n.max(turns)));
+            self.wins[winner] += 1;
+        }
+
+        for event in &state.history {
+            match event {
+                GameEvent::Roll { .. } => self.rolls += 1,
+                GameEvent::Ladder { from, to, .. } => {
+                    self.ladders += 1;
+                    *self.arrow_hits.entry((*from, *to)).or_default() += 1;
+                }
+                GameEvent::Snake { from, to, .. } => {
+                    self.snakes += 1;
+                    *self.arrow_hits.entry((*from, *to)).or_default() += 1;
+                }
+                GameEvent::Shielded { .. } => self.shields += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Games: {} ({} finished)", self.games, self.finished)?;
+        writeln!(
+            f,
+            "Turns per game: {:.1} average, {} shortest, {} longest",
+            self.average_turns(),
+            self.shortest.unwrap_or(0),
+            self.longest.unwrap_or(0)
+        )?;
+        writeln!(
+            f,
+            "Rolls: {}, ladders: {}, snakes: {}",
+            self.rolls, self.ladders, self.snakes
+        )?;
+        for ((from, to), hits) in &self.arrow_hits {
+            let kind = if to < from { "Snake" } else { "Ladder" };
+            writeln!(f, "  {} {} -> {}: {}", kind, from, to, hits)?;
+        }
+        for seat in 0..self.wins.len() {
+            writeln!(
+                f,
+                "Player {} wins {:.1}%",
+                seat + 1,
+                self.win_rate(seat) * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A game between computer players on the board the seed gives
+pub fn new_game(seed: &BigInt, players: usize, bot: Bot) -> GameState {
+    let settings = GameSettings {
+        game_id: seed.clone(),
+        max_players: players as u32,
+        players: (0..players)
+            .map(|seat| {
+                let mut profile = PlayerProfile::for_seat(seat);
+                profile.controller = Controller::Ai(bot);
+                profile
+            })
+            .collect(),
+        ..GameSettings::default()
+    };
+    let mut state = GameState::with_settings(settings);
+    state.quiet = true;
+    state
+}
+
+pub fn simulate(config: &SimulationConfig) -> Statistics {
+    let players = config.players.max(1);
+    let mut statistics = Statistics {
+        wins: vec![0; players],
+        ..Statistics::default()
+    };
+
+    for game in 0..config.games {
+        let mut state = new_game(&config.seed, players, config.bot);
+        let mut turns = 0;
+        while state.winner.is_none() && turns < MAX_TURNS {
+            state.take_turn_with_seed(turn_seed(&config.seed, game, turns));
+            turns += 1;
+        }
+        statistics.add_game(&state, turns);
+    }
+    statistics
+}
+
+fn turn_seed(seed: &BigInt, game: u32, turn: u32) -> Seed {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_bytes());
+    hasher.update(game.to_be_bytes());
+    hasher.update(turn.to_be_bytes());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+
+    #[test]
+    fn test_simulations_repeat_for_a_seed() {
+        let config = SimulationConfig {
+            seed: BigInt::from(7),
+            players: 3,
+            games: 20,
+            bot: Bot::Greedy,
+        };
+        let statistics = simulate(&config);
+        assert_eq!(statistics.games, 20);
+        assert_eq!(statistics.finished, 20);
+        assert_eq!(statistics.wins.iter().sum::<u32>(), statistics.finished);
+        assert_eq!(
+            statistics.arrow_hits.values().sum::<u64>(),
+            statistics.ladders + statistics.snakes
+        );
+        assert!(statistics.rolls > 0);
+
+        assert_eq!(simulate(&config), statistics);
+    }
+
+    #[test]
+    fn test_simulated_seats_win_as_often_as_the_analysis_says() {
+        // A short seed gives a board without special squares, which the analysis leaves out
+        let config = SimulationConfig {
+            seed: BigInt::from(5),
+            players: 3,
+            games: 2000,
+            bot: Bot::Greedy,
+        };
+        let state = new_game(&config.seed, config.players, config.bot);
+        assert!(state.special_squares.is_empty());
+
+        let statistics = simulate(&config);
+        let chances = analysis::analyze(&state).win_chances(config.players);
+        for (seat, chance) in chances.iter().enumerate() {
+            let rate = statistics.win_rate(seat);
+            assert!(
+                (rate - chance).abs() < 0.04,
+                "seat {} wins {:.3} of games, the analysis gives {:.3}",
+                seat + 1,
+                rate,
+                chance
+            );
+        }
+    }
+}
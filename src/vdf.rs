@@ -1,80 +1,419 @@
-use class_group::primitives::vdf::VDF;
 use class_group::{ABDeltaTriple, BinaryQF, pari_init};
 use curv::BigInt;
 use curv::arithmetic::traits::*;
-use curv::cryptographic_primitives::hashing::HmacExt;
+use curv::cryptographic_primitives::hashing::{DigestExt, HmacExt};
 use hmac::Hmac;
-use sha2::Sha512;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::ops::Shl;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{Receiver, Sender, channel};
-use std::thread;
-use std::time::Duration;
-
-pub fn start_search_with_stop(
-    a_b_delta: ABDeltaTriple,
-    stop_signal: Arc<AtomicBool>,
-) -> Receiver<BigInt> {
-    let (tx, rx) = channel();
-
-    thread::spawn(move || {
-        unsafe {
-            class_group::pari_init(1000000000, 2);
+use std::sync::{Mutex, Once};
+
+/// Squarings in each round of the Dig search, a find for any other count is refused
+pub const DIG_ROUND: u64 = 1 << 10;
+
+static PARI_INIT: Once = Once::new();
+// PARI's stack is shared by the whole process and is not safe to use from two threads
+// at once, every form operation holds this while it runs
+static PARI: Mutex<()> = Mutex::new(());
+// Generated discriminants by seed and size
+static DISCRIMINANTS: Mutex<BTreeMap<(BigInt, usize), BigInt>> = Mutex::new(BTreeMap::new());
+
+/// Wesolowski proof that y = g^(2^t), where g is the form generator derives from x.
+/// Stored with a VDF seeded board so anyone can check the seed was not picked by hand.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VdfProof {
+    pub x: BigInt,
+    pub t: u64,
+    #[serde(with = "form_serde")]
+    pub y: BinaryQF,
+    #[serde(with = "form_serde")]
+    pub pi: BinaryQF,
+}
+
+/// curv's BigInt serde drops the sign and form coefficients can be negative,
+/// so forms are stored as decimal strings
+pub(crate) mod form_serde {
+    use class_group::BinaryQF;
+    use curv::BigInt;
+    use curv::arithmetic::traits::Converter;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(form: &BinaryQF, serializer: S) -> Result<S::Ok, S::Error> {
+        [form.a.to_string(), form.b.to_string(), form.c.to_string()].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BinaryQF, D::Error> {
+        let [a, b, c] = <[String; 3]>::deserialize(deserializer)?;
+        let parse = |text: &str| BigInt::from_str_radix(text, 10).map_err(D::Error::custom);
+        Ok(BinaryQF {
+            a: parse(&a)?,
+            b: parse(&b)?,
+            c: parse(&c)?,
+        })
+    }
+}
+
+/// Evaluates the VDF on input x for t squarings, returns the output y and its proof.
+/// Algorithms 3 and 4 from https://eprint.iacr.org/2018/623.pdf, as in VDF::eval.
+/// VDF::eval is not used because its hash_to_prime relies on a Miller-Rabin test that
+/// randomly rejects primes, so the prover and verifier can end up with different l.
+pub fn eval(x: &BigInt, t: u64) -> (BinaryQF, BinaryQF) {
+    let never = AtomicBool::new(false);
+    eval_with_progress(&builtin_discriminant(), x, t, &never, |_| {})
+        .expect("evaluation cannot be stopped")
+}
+
+/// eval in the class group of disc that calls progress with the number of squarings
+/// done after each one, and gives up with None as soon as stop is set
+pub fn eval_with_progress(
+    disc: &BigInt,
+    x: &BigInt,
+    t: u64,
+    stop: &AtomicBool,
+    mut progress: impl FnMut(u64),
+) -> Option<(BinaryQF, BinaryQF)> {
+    let g = first_form(disc, x);
+    eval_from(disc, x, t, 0, g, stop, |iteration, _| progress(iteration))
+}
+
+/// Picks up an evaluation that already got to y after done squarings, as saved by a
+/// checkpoint. progress also gets the current form so the caller can save it again.
+/// A wrong y is not noticed here, it gives a proof that fails to verify.
+pub fn eval_from(
+    disc: &BigInt,
+    x: &BigInt,
+    t: u64,
+    done: u64,
+    y: BinaryQF,
+    stop: &AtomicBool,
+    mut progress: impl FnMut(u64, &BinaryQF),
+) -> Option<(BinaryQF, BinaryQF)> {
+    let a_b_delta = custom_setup(disc, x);
+    let g = with_pari(|| generator(&a_b_delta, x));
+
+    // The lock is taken a step at a time so a long run never holds up a quick check
+    let mut y = y;
+    for iteration in done + 1..=t {
+        if stop.load(Ordering::Relaxed) {
+            return None;
         }
+        y = with_pari(|| y.compose(&y).reduce());
+        progress(iteration, &y);
+    }
+    let l = hash_to_prime(&g, &y);
 
-        let g = BinaryQF::binary_quadratic_form_disc(&a_b_delta).reduce();
-        let mut y = g.clone();
-        let target_divider = BigInt::from(1000);
+    // pi = g^(2^t / l) by long division
+    let two = BigInt::from(2);
+    let mut r = BigInt::one();
+    let mut pi = BinaryQF::binary_quadratic_form_principal(&a_b_delta.delta);
+    for _ in 0..t {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        let r2 = &r * &two;
+        let b = r2.div_floor(&l);
+        r = r2.mod_floor(&l);
+        pi = with_pari(|| pi.exp(&two).compose(&g.exp(&b)).reduce());
+    }
 
-        if y.a.mod_floor(&target_divider).is_zero() {
-            if tx.send(BigInt::zero()).is_err() {
-                // Receiver dropped, exit
-                return;
-            }
+    Some((y, pi))
+}
+
+/// Checks that y = g^(2^t) for the form g generated from x, the same check as VDF::verify:
+/// pi^l * g^r == y with l = H(g, y) and r = 2^t mod l
+pub fn verify(x: &BigInt, t: u64, y: &BinaryQF, proof: &BinaryQF) -> bool {
+    verify_with(&builtin_discriminant(), x, t, y, proof)
+}
+
+/// verify in the class group of disc
+pub fn verify_with(disc: &BigInt, x: &BigInt, t: u64, y: &BinaryQF, proof: &BinaryQF) -> bool {
+    let a_b_delta = custom_setup(disc, x);
+    if y.discriminant() != a_b_delta.delta || proof.discriminant() != a_b_delta.delta {
+        return false;
+    }
+
+    with_pari(|| {
+        let g = generator(&a_b_delta, x);
+        let l = hash_to_prime(&g, y);
+        let r = BigInt::mod_pow(&BigInt::from(2), &BigInt::from(t), &l);
+        proof.exp(&l).compose(&g.exp(&r)).reduce() == *y
+    })
+}
+
+pub fn prove(x: &BigInt, t: u64) -> VdfProof {
+    let (y, pi) = eval(x, t);
+    VdfProof {
+        x: x.clone(),
+        t,
+        y,
+        pi,
+    }
+}
+
+/// Checks the proof in the class group its output belongs to. Callers compare
+/// proof.y.discriminant() with the one they expect, a prover could pick a weak one.
+pub fn verify_proof(proof: &VdfProof) -> bool {
+    verify_with(
+        &proof.y.discriminant(),
+        &proof.x,
+        proof.t,
+        &proof.y,
+        &proof.pi,
+    )
+}
+
+/// g for input x in the class group of disc, the form an evaluation starts from
+pub fn first_form(disc: &BigInt, x: &BigInt) -> BinaryQF {
+    let a_b_delta = custom_setup(disc, x);
+    with_pari(|| generator(&a_b_delta, x))
+}
+
+/// Runs f with PARI to itself, anything that reduces, composes or raises forms goes
+/// through here
+fn with_pari<T>(f: impl FnOnce() -> T) -> T {
+    init_pari();
+    let _pari = PARI.lock().unwrap();
+    f()
+}
+
+/// The starting form for input x. h_g picks a tiny c, so the form it builds reduces to
+/// one of only a few classes, raising it to a power taken from x keeps inputs apart.
+/// Callers hold the PARI lock.
+fn generator(a_b_delta: &ABDeltaTriple, x: &BigInt) -> BinaryQF {
+    let form = BinaryQF::binary_quadratic_form_disc(a_b_delta).reduce();
+    form.exp(&prng(x, 1, 256)).reduce()
+}
+
+/// The Fiat-Shamir challenge prime, hashed the same way as class_group's hash_to_prime
+/// but using GMP's deterministic next_prime
+fn hash_to_prime(u: &BinaryQF, w: &BinaryQF) -> BigInt {
+    let candidate = Sha256::new()
+        .chain_bigint(&u.a)
+        .chain_bigint(&u.b)
+        .chain_bigint(&u.c)
+        .chain_bigint(&w.a)
+        .chain_bigint(&w.b)
+        .chain_bigint(&w.c)
+        .result_bigint();
+    candidate.next_prime()
+}
+
+/// VDF input for a public text, such as a date or a phrase everyone agreed on
+pub fn input_from_text(text: &str) -> BigInt {
+    BigInt::from_bytes(&Sha256::digest(text.as_bytes()))
+}
+
+/// 192 digit game seed taken from the VDF output, the same length as a random seed
+pub fn seed_from_output(y: &BinaryQF) -> BigInt {
+    let low = BigInt::from(10).pow(191);
+    let range = &low * BigInt::from(9);
+    prng(&BigInt::from_bytes(&y.to_bytes()), 0, 640).mod_floor(&range) + low
+}
+
+/// Input for the round after the one that produced y
+pub fn next_input(y: &BinaryQF) -> BigInt {
+    BigInt::from_bytes(&Sha256::digest(&y.to_bytes()))
+}
+
+/// PARI keeps one stack for the whole process, set it up the first time it is needed
+fn init_pari() {
+    PARI_INIT.call_once(|| unsafe {
+        pari_init(1000000000, 2);
+    });
+}
+
+/// Settings for the Dig search, saved with the game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VdfConfig {
+    pub discriminant: Discriminant,
+    pub input: String, // Public text the search starts from
+    pub target: Target,
+    pub throttle_ms: u64, // Pause after every squaring to leave the CPU some room
+}
+
+impl Default for VdfConfig {
+    fn default() -> Self {
+        VdfConfig {
+            discriminant: Discriminant::Builtin,
+            input: "42".to_string(),
+            target: Target::Every,
+            throttle_ms: 5,
         }
+    }
+}
 
-        let mut iteration = BigInt::from(1);
-        let one = BigInt::from(1);
+impl VdfConfig {
+    pub fn input(&self) -> BigInt {
+        input_from_text(&self.input)
+    }
 
-        loop {
-            // Check if we should stop
-            if stop_signal.load(Ordering::Relaxed) {
-                println!("VDF search stopped at iteration {}", iteration);
-                break;
+    pub fn discriminant(&self) -> BigInt {
+        self.discriminant_until(&AtomicBool::new(false)).unwrap()
+    }
+
+    /// As discriminant, but gives up with None once stop is set
+    pub fn discriminant_until(&self, stop: &AtomicBool) -> Option<BigInt> {
+        match self.discriminant {
+            Discriminant::Builtin => Some(builtin_discriminant()),
+            Discriminant::Generated { bits } => {
+                generate_discriminant_until(&self.input(), bits, stop)
             }
+        }
+    }
+}
+
+/// The Dig rounds a game has taken in. Rounds are chained, so after the configured input
+/// only the round following the last one handed in counts, and none counts twice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DigLedger {
+    redeemed: BTreeSet<BigInt>, // Inputs of the rounds handed in
+    head: Option<BigInt>,       // Input of the round after the last one handed in
+}
 
-            y = y.compose(&y).reduce();
+impl DigLedger {
+    /// The input the next round handed in has to start from
+    pub fn head(&self, config: &VdfConfig) -> BigInt {
+        self.head.clone().unwrap_or_else(|| config.input())
+    }
 
-            if y.a.mod_floor(&target_divider).is_zero() {
-                if tx.send(iteration.clone()).is_err() {
-                    // Receiver dropped, exit
-                    break;
-                }
+    /// Checks that the proof is for a whole round that carries the chain on,
+    /// the proof itself is checked with verify_proof
+    pub fn check(&self, config: &VdfConfig, proof: &VdfProof) -> Result<(), String> {
+        if proof.t != DIG_ROUND {
+            return Err(format!(
+                "the proof is for {} squarings, not {}",
+                proof.t, DIG_ROUND
+            ));
+        }
+        if self.redeemed.contains(&proof.x) {
+            return Err("the round was already handed in".to_string());
+        }
+        if proof.x != self.head(config) {
+            return Err("the round does not carry the chain on".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, proof: &VdfProof) {
+        self.redeemed.insert(proof.x.clone());
+        self.head = Some(next_input(&proof.y));
+    }
+}
+
+/// The class group the search runs in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discriminant {
+    Builtin,                   // The fixed ~1600 bit discriminant from custom_setup
+    Generated { bits: usize }, // A fresh one of this size, seeded by the input
+}
+
+impl Discriminant {
+    pub const SIZES: [usize; 4] = [256, 512, 1024, 2048];
+}
+
+impl fmt::Display for Discriminant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Discriminant::Builtin => write!(f, "Built-in"),
+            Discriminant::Generated { bits } => write!(f, "{} bits", bits),
+        }
+    }
+}
+
+/// Which outputs of the search count as found
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Every,
+    DivisibleBy(u32), // The output's a coefficient is a multiple of this
+}
+
+impl Target {
+    pub fn matches(&self, y: &BinaryQF) -> bool {
+        match self {
+            Target::Every => true,
+            Target::DivisibleBy(divisor) => {
+                y.a.mod_floor(&BigInt::from((*divisor).max(1))).is_zero()
             }
+        }
+    }
+}
 
-            iteration += &one;
-            thread::sleep(Duration::from_millis(5)); // Adjust duration as needed
+/// A negative prime discriminant -p of the given size, p = 3 mod 4 so -p = 1 mod 4.
+/// The same seed and size always give the same discriminant, so each one is only
+/// searched for once and then served from a cache.
+pub fn generate_discriminant(seed: &BigInt, bits: usize) -> BigInt {
+    generate_discriminant_until(seed, bits, &AtomicBool::new(false)).unwrap()
+}
+
+/// As generate_discriminant, but the search gives up with None once stop is set.
+/// Nothing is cached for a search that was stopped.
+pub fn generate_discriminant_until(
+    seed: &BigInt,
+    bits: usize,
+    stop: &AtomicBool,
+) -> Option<BigInt> {
+    let key = (seed.clone(), bits);
+    if let Some(disc) = DISCRIMINANTS.lock().unwrap().get(&key) {
+        return Some(disc.clone());
+    }
+    // Searched without holding the lock, two threads racing only do the work twice
+    let disc = search_discriminant(seed, bits, stop)?;
+    DISCRIMINANTS.lock().unwrap().insert(key, disc.clone());
+    Some(disc)
+}
+
+fn search_discriminant(seed: &BigInt, bits: usize, stop: &AtomicBool) -> Option<BigInt> {
+    let four = BigInt::from(4);
+    let three = BigInt::from(3);
+    let mut i = 0;
+    loop {
+        // prng gives exactly bits bits, the next prime may carry past them
+        let p = next_prime_until(&prng(seed, i, bits), stop)?;
+        if p.bit_length() == bits && p.mod_floor(&four) == three {
+            return Some(-p);
         }
-    });
+        i += 1;
+    }
+}
 
-    rx
+/// The first prime after n, as next_prime finds it, with stop checked between candidates
+fn next_prime_until(n: &BigInt, stop: &AtomicBool) -> Option<BigInt> {
+    let two = BigInt::from(2);
+    let mut candidate = n + BigInt::one();
+    if candidate.is_even() {
+        candidate += BigInt::one();
+    }
+    while !stop.load(Ordering::Relaxed) {
+        if candidate.is_probable_prime(25) {
+            return Some(candidate);
+        }
+        candidate += &two;
+    }
+    None
 }
 
-/*
-Auxiliary functions, taken from vdf.rs */
-pub fn custom_setup(x: &BigInt) -> ABDeltaTriple {
-    let disc = BigInt::from_str_radix(
+pub fn builtin_discriminant() -> BigInt {
+    BigInt::from_str_radix(
         "-33113823931246733065610185160556059556094015405298556868184554415304275770508484956550367629901423347856001088308353083506236980600018729315119158888545170400248173152829933177518867657744268262446452892187819691675188700067377284304929290024952792667257440456310106172327122846283386191071754104113516886289900697664534434962391227639705115239359835498839137436278040307655519949916627736216445696327070203290002138952858567696222579847232658415685807710091074341642589939921525639",
         10,
-    ).unwrap();
+    ).unwrap()
+}
 
-    unsafe {
-        pari_init(1000000000, 2);
-    }
+/*
+Auxiliary functions, taken from vdf.rs */
+pub fn custom_setup(disc: &BigInt, x: &BigInt) -> ABDeltaTriple {
+    init_pari();
 
-    let (a, b) = h_g(&disc, x);
-    ABDeltaTriple { a, b, delta: disc }
+    let (a, b) = h_g(disc, x);
+    ABDeltaTriple {
+        a,
+        b,
+        delta: disc.clone(),
+    }
 }
 
 /// helper function H_G(x)
@@ -127,3 +466,143 @@ fn prng(seed: &BigInt, i: usize, bitlen: usize) -> BigInt {
     // prune to get |res| = bitlen
     res >> (res_bit_len - bitlen)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_and_verify() {
+        let x = BigInt::from(42);
+        let (y, proof) = eval(&x, 16);
+        assert!(verify(&x, 16, &y, &proof));
+
+        // Wrong t, input, output or proof are all rejected
+        assert!(!verify(&x, 15, &y, &proof));
+        assert!(!verify(&BigInt::from(43), 16, &y, &proof));
+        let (other_y, _) = eval(&x, 8);
+        assert!(!verify(&x, 16, &other_y, &proof));
+        // While 2^t is below the challenge prime the proof is the identity form
+        assert!(!verify(&x, 16, &y, &y));
+    }
+
+    #[test]
+    fn test_proof_past_challenge_size() {
+        // With 2^t above l the proof carries the quotient and a wrong one is caught
+        let x = BigInt::from(5);
+        let proof = prove(&x, 300);
+        assert!(verify_proof(&proof));
+        let mut tampered = proof.clone();
+        tampered.pi = with_pari(|| tampered.pi.compose(&tampered.pi).reduce());
+        assert!(!verify_proof(&tampered));
+    }
+
+    #[test]
+    fn test_eval_is_deterministic() {
+        let x = input_from_text("test");
+        assert_eq!(eval(&x, 10), eval(&x, 10));
+        // y is g squared t times
+        let g = first_form(&builtin_discriminant(), &x);
+        let (y, _) = eval(&x, 2);
+        let g4 = with_pari(|| {
+            let g2 = g.compose(&g).reduce();
+            g2.compose(&g2).reduce()
+        });
+        assert_eq!(y, g4);
+    }
+
+    #[test]
+    fn test_threads_take_turns_with_pari() {
+        // A check on one thread while another evaluates, as the window does while digging
+        let x = input_from_text("threads");
+        let expected = prove(&x, 64);
+        let provers: Vec<_> = (0..2)
+            .map(|_| std::thread::spawn(move || prove(&input_from_text("threads"), 64)))
+            .collect();
+        for _ in 0..8 {
+            assert!(verify_proof(&expected));
+        }
+        for prover in provers {
+            assert_eq!(prover.join().unwrap(), expected);
+        }
+    }
+
+    // Conditions for an imaginary quadratic class group with prime discriminant
+    fn check_discriminant(disc: &BigInt, bits: usize) {
+        assert!(disc < &BigInt::zero());
+        assert_eq!(disc.mod_floor(&BigInt::from(4)), BigInt::one());
+        let p = -disc;
+        assert_eq!(p.mod_floor(&BigInt::from(4)), BigInt::from(3));
+        assert_eq!(p.bit_length(), bits);
+        assert!(p.is_probable_prime(25));
+    }
+
+    #[test]
+    fn test_generated_discriminants() {
+        let never = AtomicBool::new(false);
+        let search =
+            |seed: u32, bits| search_discriminant(&BigInt::from(seed), bits, &never).unwrap();
+        for bits in [64, 128, 256] {
+            for seed in [1, 2, 42] {
+                let disc = search(seed, bits);
+                check_discriminant(&disc, bits);
+                assert_eq!(disc, search(seed, bits));
+
+                // Stepping to the next prime finds the same one next_prime does
+                let n = prng(&BigInt::from(seed), 0, bits);
+                assert_eq!(next_prime_until(&n, &never), Some(n.next_prime()));
+            }
+            // Different seeds give different groups
+            assert_ne!(search(1, bits), search(2, bits));
+        }
+        check_discriminant(&builtin_discriminant(), 1600);
+    }
+
+    #[test]
+    fn test_stopped_discriminant_search_gives_up() {
+        let seed = input_from_text("stopped search");
+        let stopped = AtomicBool::new(true);
+        assert_eq!(generate_discriminant_until(&seed, 2048, &stopped), None);
+        assert!(!DISCRIMINANTS.lock().unwrap().contains_key(&(seed, 2048)));
+    }
+
+    #[test]
+    fn test_discriminants_are_cached() {
+        let seed = input_from_text("cache test");
+        let disc = generate_discriminant(&seed, 128);
+        assert_eq!(
+            DISCRIMINANTS.lock().unwrap().get(&(seed.clone(), 128)),
+            Some(&disc)
+        );
+        assert_eq!(generate_discriminant(&seed, 128), disc);
+
+        // A cached discriminant works for the VDF
+        let x = BigInt::from(9);
+        let never = AtomicBool::new(false);
+        let (y, pi) = eval_with_progress(&disc, &x, 16, &never, |_| {}).unwrap();
+        assert_eq!(y.discriminant(), disc);
+        assert!(verify_with(&disc, &x, 16, &y, &pi));
+        assert!(!verify(&x, 16, &y, &pi));
+    }
+
+    #[test]
+    fn test_progress_and_cancel() {
+        let x = BigInt::from(7);
+        let stop = AtomicBool::new(false);
+        let mut seen = Vec::new();
+        assert!(
+            eval_with_progress(&builtin_discriminant(), &x, 5, &stop, |iteration| seen
+                .push(iteration))
+            .is_some()
+        );
+        assert_eq!(seen, vec![1, 2, 3, 4, 5]);
+
+        // Stopping partway gives no result
+        let result = eval_with_progress(&builtin_discriminant(), &x, 100, &stop, |iteration| {
+            if iteration == 10 {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert!(result.is_none());
+    }
+}